@@ -1,19 +1,21 @@
 use axum::{
     extract::{State, Path, WebSocketUpgrade, ws::{WebSocket, Message}},
     response::{Json, IntoResponse},
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
 use std::sync::Arc;
-use crate::exec::TaskManager;
-use crate::core::models::{Task, CreateTaskRequest, TaskStatus};
+use crate::exec::{AlreadyRunning, TaskManager, limits, retry, signals::parse_signal, scheduler::DEFAULT_QUEUE};
+use crate::core::models::{Task, CommandMode, CreateTaskRequest, Dependency, DiscoveredEnv, Run, TaskStatus, Schedule, ScheduleRequest, Secret, SecretRequest};
+use crate::schedules;
+use crate::secrets::{self, SecretStore};
 use crate::fs::{list_directory, read_file};
+use crate::db::tasks::insert_task;
 use sqlx::SqlitePool;
 use uuid::Uuid;
 use chrono::Utc;
-use futures::{stream::StreamExt, SinkExt};
-use std::path::PathBuf;
 use axum::http::StatusCode;
+use std::time::Duration;
 
 use crate::monitor::SystemMetrics;
 use tokio::sync::broadcast;
@@ -21,6 +23,7 @@ use tokio::sync::broadcast;
 pub struct AppState {
     pub task_manager: Arc<TaskManager>,
     pub pool: SqlitePool,
+    pub secrets: Arc<SecretStore>,
     pub monitor_tx: broadcast::Sender<SystemMetrics>,
 }
 
@@ -30,8 +33,25 @@ pub fn app_router(state: Arc<AppState>) -> Router {
     let api_routes = Router::new()
         .route("/tasks", get(list_tasks).post(create_task))
         .route("/tasks/:id", get(get_task))
+        .route("/tasks/:id/dependencies", get(get_dependencies))
+        .route("/tasks/:id/runs", get(list_runs))
+        .route("/runs/:run_id", get(get_run))
+        .route("/runs/:run_id/log", get(get_run_log))
         .route("/tasks/:id/start", post(start_task))
+        .route("/tasks/:id/enqueue", post(enqueue_task))
+        .route("/tasks/:id/dequeue", post(dequeue_task))
+        .route("/tasks/:id/stop", post(stop_task))
+        .route("/tasks/:id/pause", post(pause_task))
+        .route("/tasks/:id/resume", post(resume_task))
+        .route("/tasks/:id/signal", post(signal_task))
         .route("/tasks/:id/pty", get(pty_websocket))
+        .route("/schedules", get(list_schedules).post(create_schedule))
+        .route("/schedules/:id", get(get_schedule).put(update_schedule).delete(delete_schedule))
+        .route("/envs", get(list_envs))
+        .route("/envs/types", get(list_env_types))
+        .route("/secrets", get(list_secrets))
+        .route("/secrets/:name", put(set_secret).delete(delete_secret))
+        .route("/events", get(events_websocket))
         .route("/stats", get(stats_websocket))
         .route("/fs/ls", get(fs_ls))
         .route("/fs/read", get(fs_read));
@@ -58,6 +78,28 @@ async fn stats_websocket(
     })
 }
 
+async fn events_websocket(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    ws.on_upgrade(|mut socket| async move {
+        let mut rx = state.task_manager.subscribe();
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    if let Ok(msg) = serde_json::to_string(&event) {
+                        if socket.send(Message::Text(msg)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
 async fn list_tasks(State(state): State<Arc<AppState>>) -> Json<Vec<Task>> {
     let tasks = sqlx::query_as::<_, Task>("SELECT * FROM tasks ORDER BY created_at DESC")
         .fetch_all(&state.pool)
@@ -66,41 +108,127 @@ async fn list_tasks(State(state): State<Arc<AppState>>) -> Json<Vec<Task>> {
     Json(tasks)
 }
 
-async fn create_task(State(state): State<Arc<AppState>>, Json(payload): Json<CreateTaskRequest>) -> Json<Task> {
+async fn create_task(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateTaskRequest>,
+) -> Result<Json<Task>, (StatusCode, String)> {
+    // Dependencies can only point at tasks that already exist, which also
+    // rules out cycles.
+    for dep in &payload.depends_on {
+        let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM tasks WHERE id = ?")
+            .bind(&dep.depends_on)
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(internal_error)?;
+        if exists.is_none() {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown dependency {}", dep.depends_on)));
+        }
+    }
+
+    // Empty, as a form sends for a blank field, means unset, here and for
+    // env_name below.
+    let cwd = payload.cwd.filter(|c| !c.is_empty()).unwrap_or(".".to_string());
+    state.task_manager.envs().validate(&payload.env_type, payload.env_name.as_deref(), &cwd)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    if let Some(limits) = &payload.limits {
+        // They would only constrain the runtime's client.
+        if payload.env_type == "container" {
+            return Err((StatusCode::BAD_REQUEST, "Limits don't apply to containers; set cpus and memory_mb in the container options, or pass flags in extra_args".to_string()));
+        }
+        limits::validate(limits, state.task_manager.cgroups()).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    let mut retry_policy = payload.retry_policy;
+    if let Some(policy) = &mut retry_policy {
+        retry::normalize(policy).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    if payload.mode == CommandMode::Notebook && !payload.command.ends_with(".ipynb") {
+        return Err((StatusCode::BAD_REQUEST, "Notebook mode needs an .ipynb file as the command".to_string()));
+    }
+
+    let env_keys = payload.env.keys().chain(payload.secret_env.keys());
+    if let Some(key) = env_keys.into_iter().find(|k| k.is_empty() || k.contains(['=', '\0'])) {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid environment variable name {:?}", key)));
+    }
+    for name in payload.secret_env.values() {
+        if !state.secrets.exists(name).await.map_err(internal_error)? {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown secret {}", name)));
+        }
+    }
+
     let id = Uuid::new_v4().to_string();
     let task = Task {
         id: id.clone(),
         name: payload.name,
         command: payload.command,
         args: serde_json::to_string(&payload.args).unwrap(),
+        mode: payload.mode,
         env_type: payload.env_type,
-        env_name: payload.env_name,
-        cwd: payload.cwd.unwrap_or(".".to_string()),
+        env_name: payload.env_name.filter(|n| !n.is_empty()),
+        cwd,
         status: TaskStatus::Pending,
         created_at: Utc::now(),
         started_at: None,
         ended_at: None,
         pid: None,
         exit_code: None,
+        signal: None,
+        pty_rows: payload.rows,
+        pty_cols: payload.cols,
+        queue: payload.queue.unwrap_or_else(|| DEFAULT_QUEUE.to_string()),
+        priority: payload.priority.unwrap_or(0),
+        queued_at: None,
+        min_free_mem_mb: payload.min_free_mem_mb,
+        max_cpu_percent: payload.max_cpu_percent,
+        min_free_gpu_mem_mb: payload.min_free_gpu_mem_mb,
+        last_run_id: None,
+        retry_policy: retry_policy.map(sqlx::types::Json),
+        retry_at: None,
+        timeout_secs: payload.timeout_secs,
+        idle_timeout_secs: payload.idle_timeout_secs,
+        env: sqlx::types::Json(payload.env),
+        env_file: payload.env_file,
+        clear_env: payload.clear_env,
+        secret_env: sqlx::types::Json(payload.secret_env),
+        container: payload.container.map(sqlx::types::Json),
+        limits: payload.limits.map(sqlx::types::Json),
+        reason: None,
+        gpus: payload.gpus,
+        gpu_devices: None,
     };
 
-    sqlx::query(
-        "INSERT INTO tasks (id, name, command, args, env_type, env_name, cwd, status, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&task.id)
-    .bind(&task.name)
-    .bind(&task.command)
-    .bind(&task.args)
-    .bind(&task.env_type)
-    .bind(&task.env_name)
-    .bind(&task.cwd)
-    .bind(&task.status)
-    .bind(&task.created_at)
-    .execute(&state.pool)
-    .await
-    .unwrap();
+    let mut tx = state.pool.begin().await.map_err(internal_error)?;
 
-    Json(task)
+    insert_task(&mut tx, &task).await.map_err(internal_error)?;
+
+    for dep in &payload.depends_on {
+        sqlx::query("INSERT INTO task_dependencies (task_id, depends_on, condition) VALUES (?, ?, ?)")
+            .bind(&task.id)
+            .bind(&dep.depends_on)
+            .bind(dep.condition)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(task))
+}
+
+fn internal_error(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn get_dependencies(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Json<Vec<Dependency>> {
+    let deps = sqlx::query_as::<_, Dependency>("SELECT depends_on, condition FROM task_dependencies WHERE task_id = ?")
+        .bind(id)
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Json(deps)
 }
 
 async fn get_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Json<Option<Task>> {
@@ -112,9 +240,30 @@ async fn get_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) ->
     Json(task)
 }
 
+async fn list_runs(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Json<Vec<Run>> {
+    Json(state.task_manager.runs(&id).await.unwrap_or_default())
+}
+
+async fn get_run(State(state): State<Arc<AppState>>, Path(run_id): Path<String>) -> Json<Option<Run>> {
+    Json(state.task_manager.run(&run_id).await.unwrap_or_default())
+}
+
+async fn get_run_log(State(state): State<Arc<AppState>>, Path(run_id): Path<String>) -> impl IntoResponse {
+    let run = match state.task_manager.run(&run_id).await {
+        Ok(Some(run)) => run,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Run not found".to_string()).into_response(),
+        Err(e) => return internal_error(e).into_response(),
+    };
+    match std::fs::read(&run.log_file) {
+        Ok(bytes) => bytes.into_response(),
+        Err(e) => internal_error(e).into_response(),
+    }
+}
+
 async fn start_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> impl IntoResponse {
     match state.task_manager.spawn(&id).await {
         Ok(_) => StatusCode::OK,
+        Err(e) if e.is::<AlreadyRunning>() => StatusCode::CONFLICT,
         Err(e) => {
             eprintln!("Failed to start task: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR
@@ -122,6 +271,71 @@ async fn start_task(State(state): State<Arc<AppState>>, Path(id): Path<String>)
     }
 }
 
+async fn enqueue_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> impl IntoResponse {
+    match state.task_manager.enqueue(&id).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
+async fn dequeue_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> impl IntoResponse {
+    match state.task_manager.dequeue(&id).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct StopRequest {
+    grace_secs: Option<u64>,
+}
+
+async fn stop_task(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    payload: Option<Json<StopRequest>>,
+) -> impl IntoResponse {
+    let grace = payload.and_then(|Json(p)| p.grace_secs).map(Duration::from_secs);
+    match state.task_manager.stop(&id, grace).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
+async fn pause_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> impl IntoResponse {
+    match state.task_manager.pause(&id).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::CONFLICT, format!("{:#}", e)).into_response(),
+    }
+}
+
+async fn resume_task(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> impl IntoResponse {
+    match state.task_manager.resume(&id).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::CONFLICT, format!("{:#}", e)).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SignalRequest {
+    signal: String,
+}
+
+async fn signal_task(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<SignalRequest>,
+) -> impl IntoResponse {
+    let sig = match parse_signal(&payload.signal) {
+        Ok(sig) => sig,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    match state.task_manager.signal(&id, sig).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
 async fn pty_websocket(
     ws: WebSocketUpgrade,
     Path(id): Path<String>,
@@ -130,23 +344,252 @@ async fn pty_websocket(
     ws.on_upgrade(move |socket| handle_pty_socket(socket, id, state))
 }
 
+/// Control messages on the terminal socket. Binary frames carry raw PTY
+/// bytes in both directions; text frames carry one of these as JSON.
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum PtyControl {
+    Resize { rows: u16, cols: u16 },
+    Input { data: String },
+}
+
+async fn send_pty_error(socket: &mut WebSocket, e: anyhow::Error) -> bool {
+    let msg = serde_json::json!({ "type": "error", "message": format!("{:#}", e) });
+    socket.send(Message::Text(msg.to_string())).await.is_ok()
+}
+
 async fn handle_pty_socket(mut socket: WebSocket, id: String, state: Arc<AppState>) {
-    
-    // Let's implement Write-only for now via WS, and Read via a separate Log stream or just File Tail.
-    // Wait, xterm.js expects bidirectional.
-    
-    // Correct approach:
-    // `TaskManager` should expose a way to subscribe to output.
-    // Since we didn't implement broadcast in `TaskManager` yet, let's just allow writing to Stdin here. 
-    // And sending back "Connected" message.
-    
-    while let Some(msg) = socket.recv().await {
-        if let Ok(Message::Text(text)) = msg {
-            let _ = state.task_manager.write_stdin(&id, text.as_bytes()).await;
-        } else if let Ok(Message::Binary(bin)) = msg {
-             let _ = state.task_manager.write_stdin(&id, &bin).await;
+    let (replay, rx) = match state.task_manager.attach(&id).await {
+        Ok(attached) => attached,
+        Err(e) => {
+            tracing::error!("Failed to attach to task {}: {:?}", id, e);
+            let _ = socket.send(Message::Close(None)).await;
+            return;
+        }
+    };
+
+    if !replay.is_empty() && socket.send(Message::Binary(replay)).await.is_err() {
+        return;
+    }
+
+    // Finished tasks only get their log tail.
+    let Some(mut rx) = rx else {
+        let _ = socket.send(Message::Close(None)).await;
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            out = rx.recv() => match out {
+                Ok(data) => {
+                    if socket.send(Message::Binary(data)).await.is_err() {
+                        return;
+                    }
+                }
+                // A slow client misses some output but stays connected.
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::debug!("PTY client for task {} lagged by {} chunks", id, n);
+                }
+                // All senders are gone: the task has exited.
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let res = match serde_json::from_str::<PtyControl>(&text) {
+                        Ok(PtyControl::Resize { rows, cols }) => state.task_manager.resize(&id, rows, cols).await,
+                        Ok(PtyControl::Input { data }) => state.task_manager.write_stdin(&id, data.as_bytes()).await,
+                        Err(e) => Err(anyhow::anyhow!("Bad control message: {}", e)),
+                    };
+                    if let Err(e) = res {
+                        if !send_pty_error(&mut socket, e).await {
+                            return;
+                        }
+                    }
+                }
+                Some(Ok(Message::Binary(bin))) => {
+                    if let Err(e) = state.task_manager.write_stdin(&id, &bin).await {
+                        if !send_pty_error(&mut socket, e).await {
+                            return;
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+// Environment handlers
+async fn list_env_types(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
+    Json(state.task_manager.envs().env_types())
+}
+
+#[derive(serde::Deserialize)]
+struct DiscoverQuery {
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Cached discovery results; `?refresh=true` runs every backend's discovery
+/// again, which shells out to conda and walks the search roots.
+async fn list_envs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DiscoverQuery>,
+) -> Result<Json<Vec<DiscoveredEnv>>, (StatusCode, String)> {
+    let envs = state.task_manager.envs().clone();
+    let found = tokio::task::spawn_blocking(move || envs.discover(query.refresh))
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(found))
+}
+
+// Secret handlers
+async fn list_secrets(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Secret>>, (StatusCode, String)> {
+    state.secrets.list().await.map(Json).map_err(internal_error)
+}
+
+async fn set_secret(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(payload): Json<SecretRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if !secrets::valid_name(&name) {
+        return Err((StatusCode::BAD_REQUEST, "Secret names may only contain letters, digits, '_', '-' and '.'".to_string()));
+    }
+    state.secrets.set(&name, &payload.value).await.map_err(internal_error)?;
+    Ok(StatusCode::OK)
+}
+
+async fn delete_secret(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Result<StatusCode, (StatusCode, String)> {
+    match state.secrets.delete(&name).await.map_err(internal_error)? {
+        true => Ok(StatusCode::OK),
+        false => Err((StatusCode::NOT_FOUND, "Secret not found".to_string())),
+    }
+}
+
+// Schedule handlers
+async fn list_schedules(State(state): State<Arc<AppState>>) -> Json<Vec<Schedule>> {
+    let schedules = sqlx::query_as::<_, Schedule>("SELECT * FROM schedules ORDER BY created_at DESC")
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Json(schedules)
+}
+
+async fn get_schedule(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Json<Option<Schedule>> {
+    let schedule = sqlx::query_as::<_, Schedule>("SELECT * FROM schedules WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&state.pool)
+        .await
+        .unwrap_or_default();
+    Json(schedule)
+}
+
+/// Checks a schedule request and works out its first due time.
+async fn prepare_schedule(pool: &SqlitePool, req: &ScheduleRequest) -> Result<Option<chrono::DateTime<Utc>>, (StatusCode, String)> {
+    schedules::validate(req).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let template: Option<(String,)> = sqlx::query_as("SELECT id FROM tasks WHERE id = ?")
+        .bind(&req.task_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(internal_error)?;
+    if template.is_none() {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown task {}", req.task_id)));
+    }
+
+    if !req.enabled.unwrap_or(true) {
+        return Ok(None);
+    }
+    schedules::next_occurrence(req.cron.as_deref(), req.interval_secs, Utc::now())
+        .map(Some)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn create_schedule(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ScheduleRequest>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    let next_run_at = prepare_schedule(&state.pool, &payload).await?;
+    let schedule = Schedule {
+        id: Uuid::new_v4().to_string(),
+        name: payload.name,
+        task_id: payload.task_id,
+        cron: payload.cron,
+        interval_secs: payload.interval_secs,
+        catch_up: payload.catch_up,
+        overlap: payload.overlap,
+        enabled: payload.enabled.unwrap_or(true),
+        created_at: Utc::now(),
+        next_run_at,
+        last_run_at: None,
+        last_task_id: None,
+    };
+
+    sqlx::query(
+        "INSERT INTO schedules (id, name, task_id, cron, interval_secs, catch_up, overlap, enabled, created_at, next_run_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&schedule.id)
+    .bind(&schedule.name)
+    .bind(&schedule.task_id)
+    .bind(&schedule.cron)
+    .bind(schedule.interval_secs)
+    .bind(schedule.catch_up)
+    .bind(schedule.overlap)
+    .bind(schedule.enabled)
+    .bind(schedule.created_at)
+    .bind(schedule.next_run_at)
+    .execute(&state.pool)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(schedule))
+}
+
+async fn update_schedule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<ScheduleRequest>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    let next_run_at = prepare_schedule(&state.pool, &payload).await?;
+    let res = sqlx::query(
+        "UPDATE schedules SET name = ?, task_id = ?, cron = ?, interval_secs = ?, catch_up = ?, overlap = ?, enabled = ?, next_run_at = ? WHERE id = ?"
+    )
+    .bind(&payload.name)
+    .bind(&payload.task_id)
+    .bind(&payload.cron)
+    .bind(payload.interval_secs)
+    .bind(payload.catch_up)
+    .bind(payload.overlap)
+    .bind(payload.enabled.unwrap_or(true))
+    .bind(next_run_at)
+    .bind(&id)
+    .execute(&state.pool)
+    .await
+    .map_err(internal_error)?;
+    if res.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Schedule not found".to_string()));
+    }
+
+    let schedule = sqlx::query_as::<_, Schedule>("SELECT * FROM schedules WHERE id = ?")
+        .bind(&id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(schedule))
+}
+
+async fn delete_schedule(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> impl IntoResponse {
+    match sqlx::query("DELETE FROM schedules WHERE id = ?").bind(id).execute(&state.pool).await {
+        Ok(res) if res.rows_affected() == 0 => StatusCode::NOT_FOUND,
+        Ok(_) => StatusCode::OK,
+        Err(e) => {
+            tracing::error!("Failed to delete schedule: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
         }
-        // TODO: Handle resize message (JSON)
     }
 }
 
@@ -1,17 +1,77 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use std::collections::HashMap;
+use sqlx::types::Json;
 use chrono::{DateTime, Utc};
-use uuid::Uuid;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
 #[sqlx(type_name = "TEXT")]
 pub enum TaskStatus {
     Pending,
+    Queued,
     Running,
+    Paused, // suspended by the user, still holding its memory
     Completed,
     Failed,
     Stopped,
+    Skipped, // a dependency ended in a way that rules this task out
+    Retrying, // failed, waiting out the backoff before the next attempt
+    TimedOut, // killed for running too long or going quiet
+    Lost, // the server restarted and the process ended unobserved
+}
+
+impl TaskStatus {
+    pub fn is_terminal(self) -> bool {
+        matches!(self, TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Stopped | TaskStatus::Skipped | TaskStatus::TimedOut | TaskStatus::Lost)
+    }
+}
+
+/// When a dependency counts as met, based on how the upstream task ended.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, sqlx::Type)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyCondition {
+    #[default]
+    Success,
+    Failure,
+    Always,
+}
+
+impl DependencyCondition {
+    /// `None` while the upstream task hasn't finished.
+    pub fn satisfied_by(self, upstream: TaskStatus) -> Option<bool> {
+        if !upstream.is_terminal() {
+            return None;
+        }
+        Some(match self {
+            DependencyCondition::Success => upstream == TaskStatus::Completed,
+            DependencyCondition::Failure => matches!(upstream, TaskStatus::Failed | TaskStatus::TimedOut | TaskStatus::Lost),
+            DependencyCondition::Always => true,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct Dependency {
+    pub depends_on: String,
+    #[serde(default)]
+    pub condition: DependencyCondition,
+}
+
+/// How `command` and `args` become the process that runs inside the
+/// task's environment.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, sqlx::Type)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CommandMode {
+    /// `command` is a shell script run with `sh -c`; `args` become `$1`...
+    #[default]
+    Shell,
+    /// `command` is the program and `args` its argv, passed verbatim.
+    Exec,
+    /// `command` is an `.ipynb` file, executed headless with nbconvert into
+    /// a new notebook next to it; `args` are passed to nbconvert.
+    Notebook,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
@@ -20,6 +80,7 @@ pub struct Task {
     pub name: String,
     pub command: String,
     pub args: String, // JSON array of strings
+    pub mode: CommandMode,
     pub env_type: String, // "shell", "conda", "uv", "jupyter"
     pub env_name: Option<String>, // e.g. "my-env" or path
     pub cwd: String,
@@ -29,6 +90,115 @@ pub struct Task {
     pub ended_at: Option<DateTime<Utc>>,
     pub pid: Option<u32>,
     pub exit_code: Option<i32>,
+    pub signal: Option<String>, // signal that ended the task, if any
+    pub pty_rows: Option<u16>,
+    pub pty_cols: Option<u16>,
+    pub queue: String,
+    pub priority: i32, // higher runs first
+    pub queued_at: Option<DateTime<Utc>>,
+    // Admission constraints, checked against the monitor before starting
+    pub min_free_mem_mb: Option<i64>,
+    pub max_cpu_percent: Option<f32>,
+    pub min_free_gpu_mem_mb: Option<i64>,
+    pub last_run_id: Option<String>,
+    pub retry_policy: Option<Json<RetryPolicy>>,
+    pub retry_at: Option<DateTime<Utc>>, // when a Retrying task may start its next attempt
+    pub timeout_secs: Option<u32>, // wall-clock limit per run
+    pub idle_timeout_secs: Option<u32>, // longest stretch without PTY output
+    pub env: Json<BTreeMap<String, String>>,
+    pub env_file: Option<String>, // .env style file, relative to cwd
+    pub clear_env: bool, // drop the server's environment, all but PATH
+    pub secret_env: Json<BTreeMap<String, String>>, // env var -> secret name
+    pub container: Option<Json<ContainerOptions>>, // for env_type "container"
+    pub limits: Option<Json<ResourceLimits>>,
+    pub reason: Option<String>, // why the latest run ended, when status and signal don't say
+    pub gpus: Option<u32>, // whole GPUs to reserve; the task sees them in CUDA_VISIBLE_DEVICES
+    pub gpu_devices: Option<String>, // what the latest run got, as in CUDA_VISIBLE_DEVICES
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    #[default]
+    Fixed,
+    Exponential,
+}
+
+fn default_retry_delay() -> u64 {
+    10
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    #[serde(default)]
+    pub backoff: Backoff,
+    #[serde(default = "default_retry_delay")]
+    pub delay_secs: u64, // first delay; doubled per retry when exponential
+    pub max_delay_secs: Option<u64>,
+    #[serde(default)]
+    pub jitter: bool,
+    #[serde(default)]
+    pub exit_codes: Vec<i32>, // retry on these codes; empty means any non-zero code
+    #[serde(default)]
+    pub signals: Vec<String>, // e.g. "SIGKILL", "KILL" or "9", stored by name; deaths by other signals are final
+}
+
+/// Resource limits and extra settings for tasks run in a container.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContainerOptions {
+    pub cpus: Option<f64>, // --cpus, e.g. 1.5
+    pub memory_mb: Option<u32>, // --memory
+    pub gpus: Option<String>, // --gpus, e.g. "all" or "device=0,1"
+    pub network: Option<String>, // --network
+    #[serde(default)]
+    pub extra_args: Vec<String>, // passed to `run` as given, e.g. ["--shm-size", "2g"]
+}
+
+/// Limits on a task's processes. Enforced through a cgroup of its own when
+/// the server has a cgroup v2 subtree delegated to it; otherwise memory falls
+/// back to an rlimit, the CPU settings are ignored and `max_pids` is refused.
+/// Containers take theirs from `ContainerOptions` instead.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ResourceLimits {
+    pub memory_mb: Option<u32>, // memory.max; RLIMIT_DATA without cgroups
+    pub cpu_percent: Option<u32>, // cpu.max, 100 = one full core
+    pub cpu_weight: Option<u32>, // cpu.weight, 1 to 10000, default 100
+    pub max_pids: Option<u32>, // pids.max; needs cgroups
+    pub nice: Option<i32>, // -20 (most favourable) to 19
+    pub io_class: Option<IoClass>,
+    pub io_level: Option<u8>, // 0 (highest) to 7, for realtime and best-effort
+}
+
+/// I/O scheduling class, as for `ionice -c`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+/// One execution of a task. The runtime columns on `Task` mirror its latest
+/// run; earlier attempts are only kept here.
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct Run {
+    pub id: String,
+    pub task_id: String,
+    pub attempt: i64, // 1 for the first run of a task
+    pub retry: i64, // 0 unless started by the retry policy
+    pub status: TaskStatus,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub pid: Option<u32>,
+    /// Kernel start time of `pid`, used to tell it apart from a reused pid.
+    #[serde(skip)]
+    pub pid_start_time: Option<i64>,
+    pub exit_code: Option<i32>,
+    pub signal: Option<String>,
+    pub reason: Option<String>,
+    pub log_file: String,
+    pub gpu_devices: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,7 +206,197 @@ pub struct CreateTaskRequest {
     pub name: String,
     pub command: String,
     pub args: Vec<String>,
+    #[serde(default)]
+    pub mode: CommandMode,
     pub env_type: String,
     pub env_name: Option<String>,
     pub cwd: Option<String>,
+    pub rows: Option<u16>, // initial PTY size, defaults to 24x80
+    pub cols: Option<u16>,
+    pub queue: Option<String>,
+    pub priority: Option<i32>,
+    pub min_free_mem_mb: Option<i64>,
+    pub max_cpu_percent: Option<f32>,
+    pub min_free_gpu_mem_mb: Option<i64>,
+    #[serde(default)]
+    pub depends_on: Vec<Dependency>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub timeout_secs: Option<u32>,
+    pub idle_timeout_secs: Option<u32>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub env_file: Option<String>,
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Environment variables filled from the secrets store at spawn, as
+    /// variable name -> secret name.
+    #[serde(default)]
+    pub secret_env: BTreeMap<String, String>,
+    pub container: Option<ContainerOptions>,
+    pub limits: Option<ResourceLimits>,
+    pub gpus: Option<u32>,
+}
+
+/// Emitted by the supervisor whenever a task changes state.
+#[derive(Debug, Serialize, Clone)]
+pub struct TaskEvent {
+    pub task_id: String,
+    pub run_id: Option<String>,
+    pub status: TaskStatus,
+    pub exit_code: Option<i32>,
+    pub signal: Option<String>,
+}
+
+/// What to do when the server was down or busy over one or more due times.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, sqlx::Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    /// Drop the missed runs and wait for the next due time.
+    #[default]
+    Skip,
+    /// Start one run now, however many due times were missed.
+    RunOnce,
+}
+
+/// What to do when a run is due while the previous one is still active.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, sqlx::Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    #[default]
+    Skip,
+    Allow,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    pub task_id: String, // template task; each run is a copy of it
+    pub cron: Option<String>, // standard 5-field expression, local time
+    pub interval_secs: Option<i64>, // used when cron is not set
+    pub catch_up: CatchUpPolicy,
+    pub overlap: OverlapPolicy,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_task_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduleRequest {
+    pub name: String,
+    pub task_id: String,
+    pub cron: Option<String>,
+    pub interval_secs: Option<i64>,
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+    pub enabled: Option<bool>,
+}
+
+/// A stored secret as the API shows it: never the value.
+#[derive(Debug, Serialize, Clone, FromRow)]
+pub struct Secret {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SecretRequest {
+    pub value: String,
+}
+
+/// An environment found on this machine, ready to use as a task's
+/// `env_type`/`env_name`.
+#[derive(Debug, Serialize, Clone)]
+pub struct DiscoveredEnv {
+    pub env_type: String,
+    pub name: String,
+    pub path: Option<String>,
+    // From the kernel spec, for jupyter kernels
+    pub display_name: Option<String>,
+    pub language: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// A plain shell task with nothing else set, for tests to adjust.
+#[cfg(test)]
+pub fn test_task() -> Task {
+    Task {
+        id: "t".into(),
+        name: "t".into(),
+        command: "true".into(),
+        args: "[]".into(),
+        mode: CommandMode::Shell,
+        env_type: "shell".into(),
+        env_name: None,
+        cwd: ".".into(),
+        status: TaskStatus::Pending,
+        created_at: Utc::now(),
+        started_at: None,
+        ended_at: None,
+        pid: None,
+        exit_code: None,
+        signal: None,
+        pty_rows: None,
+        pty_cols: None,
+        queue: "default".into(),
+        priority: 0,
+        queued_at: None,
+        min_free_mem_mb: None,
+        max_cpu_percent: None,
+        min_free_gpu_mem_mb: None,
+        last_run_id: None,
+        retry_policy: None,
+        retry_at: None,
+        timeout_secs: None,
+        idle_timeout_secs: None,
+        env: Json(BTreeMap::new()),
+        env_file: None,
+        clear_env: false,
+        secret_env: Json(BTreeMap::new()),
+        container: None,
+        limits: None,
+        reason: None,
+        gpus: None,
+        gpu_devices: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DependencyCondition::*;
+    use TaskStatus::*;
+
+    #[test]
+    fn waits_for_unfinished_upstream() {
+        for upstream in [Pending, Queued, Running, Paused, Retrying] {
+            for condition in [Success, Failure, Always] {
+                assert_eq!(condition.satisfied_by(upstream), None, "{:?} on {:?}", condition, upstream);
+            }
+        }
+    }
+
+    #[test]
+    fn conditions_on_finished_upstream() {
+        let cases = [
+            (Completed, [true, false, true]),
+            (Failed, [false, true, true]),
+            (TimedOut, [false, true, true]),
+            (Lost, [false, true, true]),
+            (Stopped, [false, false, true]),
+            (Skipped, [false, false, true]),
+        ];
+        for (upstream, expected) in cases {
+            for (condition, want) in [Success, Failure, Always].into_iter().zip(expected) {
+                assert_eq!(condition.satisfied_by(upstream), Some(want), "{:?} on {:?}", condition, upstream);
+            }
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnection, SqlitePool, SqlitePoolOptions};
 use std::fs;
 use std::path::Path;
 
@@ -22,6 +22,11 @@ pub async fn init_db(db_url: &str) -> Result<SqlitePool, sqlx::Error> {
         .max_connections(5)
         .connect(db_url).await?;
 
+    // Run all schema changes on one connection. SQLite connections cache the
+    // schema, and one that read it before an ALTER TABLE elsewhere will hand
+    // sqlx a stale column list for `SELECT *`.
+    let mut conn = pool.acquire().await?;
+
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS tasks (
@@ -41,8 +46,125 @@ pub async fn init_db(db_url: &str) -> Result<SqlitePool, sqlx::Error> {
         );
         "#
     )
-    .execute(&pool)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS task_dependencies (
+            task_id TEXT NOT NULL REFERENCES tasks(id),
+            depends_on TEXT NOT NULL REFERENCES tasks(id),
+            condition TEXT NOT NULL,
+            PRIMARY KEY (task_id, depends_on)
+        );
+        "#
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schedules (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            task_id TEXT NOT NULL REFERENCES tasks(id),
+            cron TEXT,
+            interval_secs INTEGER,
+            catch_up TEXT NOT NULL,
+            overlap TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL,
+            created_at DATETIME NOT NULL,
+            next_run_at DATETIME,
+            last_run_at DATETIME,
+            last_task_id TEXT
+        );
+        "#
+    )
+    .execute(&mut *conn)
     .await?;
 
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS secrets (
+            name TEXT PRIMARY KEY,
+            value BLOB NOT NULL, -- nonce followed by ciphertext
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL
+        );
+        "#
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS runs (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL REFERENCES tasks(id),
+            attempt INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            started_at DATETIME NOT NULL,
+            ended_at DATETIME,
+            pid INTEGER,
+            exit_code INTEGER,
+            signal TEXT,
+            log_file TEXT NOT NULL
+        );
+        "#
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS runs_task_id ON runs (task_id, attempt)")
+        .execute(&mut *conn)
+        .await?;
+
+    add_column(&mut conn, "tasks", "signal", "TEXT").await?;
+    add_column(&mut conn, "tasks", "pty_rows", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "pty_cols", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "queue", "TEXT NOT NULL DEFAULT 'default'").await?;
+    add_column(&mut conn, "tasks", "priority", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column(&mut conn, "tasks", "queued_at", "DATETIME").await?;
+    add_column(&mut conn, "tasks", "min_free_mem_mb", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "max_cpu_percent", "REAL").await?;
+    add_column(&mut conn, "tasks", "min_free_gpu_mem_mb", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "last_run_id", "TEXT").await?;
+    add_column(&mut conn, "tasks", "retry_policy", "TEXT").await?;
+    add_column(&mut conn, "tasks", "timeout_secs", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "idle_timeout_secs", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "mode", "TEXT NOT NULL DEFAULT 'shell'").await?;
+    add_column(&mut conn, "tasks", "env", "TEXT NOT NULL DEFAULT '{}'").await?;
+    add_column(&mut conn, "tasks", "env_file", "TEXT").await?;
+    add_column(&mut conn, "tasks", "clear_env", "BOOLEAN NOT NULL DEFAULT 0").await?;
+    add_column(&mut conn, "tasks", "secret_env", "TEXT NOT NULL DEFAULT '{}'").await?;
+    add_column(&mut conn, "tasks", "container", "TEXT").await?;
+    add_column(&mut conn, "tasks", "limits", "TEXT").await?;
+    add_column(&mut conn, "tasks", "reason", "TEXT").await?;
+    add_column(&mut conn, "tasks", "gpus", "INTEGER").await?;
+    add_column(&mut conn, "tasks", "gpu_devices", "TEXT").await?;
+    add_column(&mut conn, "tasks", "retry_at", "DATETIME").await?;
+    add_column(&mut conn, "runs", "retry", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column(&mut conn, "runs", "pid_start_time", "INTEGER").await?;
+    add_column(&mut conn, "runs", "reason", "TEXT").await?;
+    add_column(&mut conn, "runs", "gpu_devices", "TEXT").await?;
+
+    drop(conn);
     Ok(pool)
 }
+
+/// `CREATE TABLE IF NOT EXISTS` won't touch databases created by older
+/// builds, so new columns are added here when missing.
+async fn add_column(conn: &mut SqliteConnection, table: &str, column: &str, decl: &str) -> Result<(), sqlx::Error> {
+    let existing: Option<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    if existing.is_none() {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
@@ -1 +1,2 @@
 pub mod init;
+pub mod tasks;
@@ -0,0 +1,40 @@
+use sqlx::sqlite::SqliteConnection;
+use crate::core::models::Task;
+
+/// Insert a new task row. Runtime columns (pid, timings, exit status) start
+/// out NULL.
+pub async fn insert_task(conn: &mut SqliteConnection, task: &Task) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO tasks (id, name, command, args, mode, env_type, env_name, cwd, status, created_at, pty_rows, pty_cols, queue, priority, min_free_mem_mb, max_cpu_percent, min_free_gpu_mem_mb, retry_policy, timeout_secs, idle_timeout_secs, env, env_file, clear_env, secret_env, container, limits, gpus) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&task.id)
+    .bind(&task.name)
+    .bind(&task.command)
+    .bind(&task.args)
+    .bind(task.mode)
+    .bind(&task.env_type)
+    .bind(&task.env_name)
+    .bind(&task.cwd)
+    .bind(task.status)
+    .bind(task.created_at)
+    .bind(task.pty_rows)
+    .bind(task.pty_cols)
+    .bind(&task.queue)
+    .bind(task.priority)
+    .bind(task.min_free_mem_mb)
+    .bind(task.max_cpu_percent)
+    .bind(task.min_free_gpu_mem_mb)
+    .bind(&task.retry_policy)
+    .bind(task.timeout_secs)
+    .bind(task.idle_timeout_secs)
+    .bind(&task.env)
+    .bind(&task.env_file)
+    .bind(task.clear_env)
+    .bind(&task.secret_env)
+    .bind(&task.container)
+    .bind(&task.limits)
+    .bind(task.gpus)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
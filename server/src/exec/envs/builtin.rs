@@ -0,0 +1,675 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::process::Command;
+use anyhow::{Result, anyhow, Context};
+use serde::Deserialize;
+use crate::core::models::{CommandMode, DiscoveredEnv, Task};
+use super::{EnvironmentBackend, Launch};
+
+/// Runs the argv directly with the server's environment.
+pub struct Shell;
+
+impl EnvironmentBackend for Shell {
+    fn build_command(&self, _task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        Ok(Launch::argv(argv))
+    }
+}
+
+/// `conda run -n <env>`, and the same for mamba and micromamba, which share
+/// conda's command line.
+pub struct Conda {
+    pub binary: &'static str,
+}
+
+impl EnvironmentBackend for Conda {
+    fn build_command(&self, task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        let env_name = task.env_name.as_ref().ok_or_else(|| anyhow!("Environment name required for {}", self.binary))?;
+
+        // --no-capture-output is important for pty interaction in some
+        // versions, but run typically execs.
+        let mut args = vec![
+            "run".to_string(),
+            "-n".to_string(),
+            env_name.clone(),
+            "--no-capture-output".to_string(),
+        ];
+        args.extend(argv);
+
+        Ok(Launch::new(self.binary, args))
+    }
+
+    fn validate(&self, env_name: Option<&str>, _cwd: &str) -> Result<()> {
+        env_name.ok_or_else(|| anyhow!("Environment name required for {}", self.binary))?;
+        Ok(())
+    }
+
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        #[derive(Deserialize)]
+        struct EnvList {
+            envs: Vec<PathBuf>,
+        }
+
+        let output = match Command::new(self.binary).args(["env", "list", "--json"]).output() {
+            Ok(output) => output,
+            // Not installed here; nothing to discover.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", self.binary)),
+        };
+        if !output.status.success() {
+            return Err(anyhow!("{} env list failed: {}", self.binary, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        let list: EnvList = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Unexpected output from {} env list", self.binary))?;
+
+        Ok(list.envs.into_iter().map(|path| {
+            // Named envs live in <prefix>/envs/<name>; anything else is
+            // the root prefix, which `-n` knows as base.
+            let name = match (path.parent().and_then(Path::file_name), path.file_name()) {
+                (Some(parent), Some(name)) if parent == "envs" => name.to_string_lossy().into_owned(),
+                _ => "base".to_string(),
+            };
+            DiscoveredEnv {
+                env_type: self.binary.to_string(),
+                name,
+                path: Some(path.to_string_lossy().into_owned()),
+                display_name: None,
+                language: None,
+                metadata: None,
+            }
+        }).collect())
+    }
+}
+
+/// How deep below a search root to look for venvs and projects.
+const SEARCH_DEPTH: usize = 3;
+
+/// Directories under `roots`, at most `SEARCH_DEPTH` levels down, that
+/// contain `marker`. Hidden directories other than `.venv` and
+/// `node_modules` are not descended into, nor is anything already matched.
+fn find_dirs(roots: &[PathBuf], marker: &str) -> Vec<PathBuf> {
+    fn walk(dir: &Path, marker: &str, depth: usize, found: &mut Vec<PathBuf>) {
+        if dir.join(marker).exists() {
+            found.push(dir.to_path_buf());
+            return;
+        }
+        if depth == 0 {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if (name.starts_with('.') && name != ".venv") || name == "node_modules" {
+                continue;
+            }
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                walk(&entry.path(), marker, depth - 1, found);
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    for root in roots {
+        walk(root, marker, SEARCH_DEPTH, &mut found);
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// `uv run`. UV is usually project local: if cwd has pyproject.toml, uv run
+/// works.
+pub struct Uv {
+    /// Where discovery looks for projects (directories with a `uv.lock`).
+    pub roots: Vec<PathBuf>,
+}
+
+impl EnvironmentBackend for Uv {
+    fn build_command(&self, _task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        let mut args = vec!["run".to_string(), "--".to_string()];
+        args.extend(argv);
+        Ok(Launch::new("uv", args))
+    }
+
+    /// A uv project is picked by cwd, so `path` is what matters here.
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        Ok(find_dirs(&self.roots, "uv.lock").into_iter().map(|dir| DiscoveredEnv {
+            env_type: "uv".to_string(),
+            name: dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            path: Some(dir.to_string_lossy().into_owned()),
+            display_name: None,
+            language: None,
+            metadata: None,
+        }).collect())
+    }
+}
+
+/// `PATH` with `dir` in front of the server's own.
+fn prepend_path(dir: &Path) -> String {
+    let path_var = std::env::var("PATH").unwrap_or_default();
+    format!("{}:{}", dir.to_string_lossy(), path_var)
+}
+
+/// A kernel's `kernel.json`, as jupyter reads it.
+#[derive(Debug, Deserialize)]
+struct KernelSpec {
+    argv: Vec<String>,
+    display_name: String,
+    language: String,
+    /// Set for the kernel process; values may refer to other variables as
+    /// `$NAME` or `${NAME}`.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+}
+
+impl KernelSpec {
+    fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read kernel spec {}", path.display()))?;
+        let spec: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid kernel spec {}", path.display()))?;
+        if spec.argv.is_empty() {
+            return Err(anyhow!("Empty argv in kernel spec {}", path.display()));
+        }
+        Ok(spec)
+    }
+
+    /// The kernel's interpreter, when the spec gives its path. Usually argv
+    /// looks like ["/path/to/python", "-m", "ipykernel_launcher", "-f",
+    /// "{connection_file}"]; a bare `python` means whichever is on PATH.
+    fn interpreter(&self) -> Option<&Path> {
+        Some(Path::new(self.argv.first()?)).filter(|p| p.is_absolute())
+    }
+}
+
+/// Expands `$NAME` and `${NAME}` the way jupyter does for a kernel's `env`
+/// (Python's `Template.safe_substitute`): `$$` is a literal `$`, and
+/// unknown names are left as they are.
+fn substitute(value: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start + 1..];
+        if let Some(after) = tail.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, len) = match tail.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = tail.find(|c: char| !is_ident(c)).unwrap_or(tail.len());
+                (&tail[..end], end)
+            }
+        };
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(is_ident);
+        match valid.then(|| lookup(name)).flatten() {
+            Some(found) => {
+                out.push_str(&found);
+                rest = &tail[len..];
+            }
+            None => {
+                out.push('$');
+                rest = tail;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Runs the argv the way a jupyter kernel would be: with the kernel's
+/// interpreter first on PATH and its `env` applied. The kernel spec path is
+/// stored in `env_name`.
+///
+/// In notebook mode the notebook is executed against this kernel.
+pub struct Jupyter;
+
+impl Jupyter {
+    /// Where jupyter looks for kernels, highest precedence first:
+    /// `JUPYTER_PATH`, the user data dir, then the system-wide ones.
+    fn kernel_dirs() -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        if let Some(path) = std::env::var_os("JUPYTER_PATH") {
+            dirs.extend(std::env::split_paths(&path));
+        }
+        match std::env::var_os("JUPYTER_DATA_DIR") {
+            Some(dir) => dirs.push(PathBuf::from(dir)),
+            None => if let Some(home) = std::env::var_os("HOME") {
+                dirs.push(PathBuf::from(home).join(".local/share/jupyter"));
+            },
+        }
+        dirs.push(PathBuf::from("/usr/local/share/jupyter"));
+        dirs.push(PathBuf::from("/usr/share/jupyter"));
+        dirs.into_iter().map(|d| d.join("kernels")).collect()
+    }
+
+    /// The kernel's name and the data dir it is found under, from a spec
+    /// path of the form `<data dir>/kernels/<name>/kernel.json`. nbconvert
+    /// only takes kernels by name.
+    fn kernel_location(spec_path: &Path) -> Result<(String, PathBuf)> {
+        let kernel_dir = spec_path.parent().filter(|d| d.parent().and_then(Path::file_name) == Some("kernels".as_ref()));
+        match kernel_dir.and_then(|d| Some((d.file_name()?, d.parent()?.parent()?))) {
+            Some((name, data_dir)) => Ok((name.to_string_lossy().into_owned(), data_dir.to_path_buf())),
+            None => Err(anyhow!("Kernel spec {} must be at kernels/<name>/kernel.json to run notebooks", spec_path.display())),
+        }
+    }
+}
+
+impl EnvironmentBackend for Jupyter {
+    fn build_command(&self, task: &Task, mut argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        let spec_path = Path::new(task.env_name.as_ref().ok_or_else(|| anyhow!("Kernel path required"))?);
+        let spec = KernelSpec::load(spec_path)?;
+
+        let mut env = Vec::new();
+        if let Some(python) = spec.interpreter() {
+            let bin_dir = python.parent().ok_or_else(|| anyhow!("Invalid interpreter path {}", python.display()))?;
+            env.push(("PATH".to_string(), prepend_path(bin_dir)));
+            // A bare python would be found on that PATH anyway; naming the
+            // kernel's keeps it even if the task's env changes PATH again.
+            if task.mode == CommandMode::Exec && matches!(argv[0].as_str(), "python" | "python3") {
+                argv[0] = python.to_string_lossy().into_owned();
+            }
+        }
+
+        // Later entries see earlier ones, so `${PATH}` includes the above.
+        for (key, value) in &spec.env {
+            let value = substitute(value, |name| {
+                env.iter().rev().find(|(k, _)| k == name).map(|(_, v)| v.clone()).or_else(|| std::env::var(name).ok())
+            });
+            env.push((key.clone(), value));
+        }
+
+        if task.mode == CommandMode::Notebook {
+            let (name, data_dir) = Self::kernel_location(spec_path)?;
+            argv.push(format!("--ExecutePreprocessor.kernel_name={}", name));
+            let jupyter_path = match std::env::var("JUPYTER_PATH") {
+                Ok(path) if !path.is_empty() => format!("{}:{}", data_dir.to_string_lossy(), path),
+                _ => data_dir.to_string_lossy().into_owned(),
+            };
+            env.push(("JUPYTER_PATH".to_string(), jupyter_path));
+        }
+
+        let mut launch = Launch::argv(argv);
+        launch.env = env;
+        Ok(launch)
+    }
+
+    fn validate(&self, env_name: Option<&str>, _cwd: &str) -> Result<()> {
+        let kernel_path = env_name.ok_or_else(|| anyhow!("Kernel path required"))?;
+        if !Path::new(kernel_path).is_file() {
+            return Err(anyhow!("Kernel spec {} not found", kernel_path));
+        }
+        Ok(())
+    }
+
+    /// One entry per kernel name; like jupyter, the first directory that
+    /// has a kernel wins. `name` is the kernel.json path tasks expect.
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        let mut seen = std::collections::HashSet::new();
+        let mut found = Vec::new();
+        for dir in Self::kernel_dirs() {
+            let Ok(entries) = fs::read_dir(&dir) else { continue };
+            let mut kernels: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+            kernels.sort();
+            for kernel_dir in kernels {
+                let Some(kernel) = kernel_dir.file_name().map(|n| n.to_string_lossy().into_owned()) else { continue };
+                let spec_path = kernel_dir.join("kernel.json");
+                let Ok(spec) = KernelSpec::load(&spec_path) else { continue };
+                if !seen.insert(kernel) {
+                    continue;
+                }
+                found.push(DiscoveredEnv {
+                    env_type: "jupyter".to_string(),
+                    name: spec_path.to_string_lossy().into_owned(),
+                    path: spec.argv.first().cloned(),
+                    display_name: Some(spec.display_name),
+                    language: Some(spec.language),
+                    metadata: spec.metadata,
+                });
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// A plain virtualenv, activated through the environment alone.
+pub struct Venv {
+    /// Where discovery looks for virtualenvs (directories with a
+    /// `pyvenv.cfg`).
+    pub roots: Vec<PathBuf>,
+}
+
+impl Venv {
+    /// Relative paths are taken from the task's cwd.
+    fn dir(task: &Task) -> Result<PathBuf> {
+        let name = task.env_name.as_ref().ok_or_else(|| anyhow!("Virtualenv path required"))?;
+        Ok(Path::new(&task.cwd).join(name))
+    }
+}
+
+impl EnvironmentBackend for Venv {
+    fn build_command(&self, task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        // What `source bin/activate` does, minus the prompt.
+        let dir = Self::dir(task)?;
+        let dir = dir.canonicalize().unwrap_or(dir);
+        let mut launch = Launch::argv(argv);
+        launch.env = vec![
+            ("PATH".to_string(), prepend_path(&dir.join("bin"))),
+            ("VIRTUAL_ENV".to_string(), dir.to_string_lossy().into_owned()),
+        ];
+        Ok(launch)
+    }
+
+    fn validate(&self, env_name: Option<&str>, cwd: &str) -> Result<()> {
+        let dir = Path::new(cwd).join(env_name.ok_or_else(|| anyhow!("Virtualenv path required"))?);
+        if !dir.join("bin").join("python").exists() {
+            return Err(anyhow!("{} is not a virtualenv (no bin/python)", dir.display()));
+        }
+        Ok(())
+    }
+
+    /// `name` is the venv's absolute path, which works from any cwd.
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        Ok(find_dirs(&self.roots, "pyvenv.cfg").into_iter().map(|dir| DiscoveredEnv {
+            env_type: "venv".to_string(),
+            name: dir.to_string_lossy().into_owned(),
+            path: Some(dir.to_string_lossy().into_owned()),
+            display_name: None,
+            language: None,
+            metadata: None,
+        }).collect())
+    }
+}
+
+/// `poetry run` in the project's cwd.
+pub struct Poetry;
+
+impl EnvironmentBackend for Poetry {
+    fn build_command(&self, _task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        let mut args = vec!["run".to_string()];
+        args.extend(argv);
+        Ok(Launch::new("poetry", args))
+    }
+
+    fn validate(&self, _env_name: Option<&str>, cwd: &str) -> Result<()> {
+        if !Path::new(cwd).join("pyproject.toml").is_file() {
+            return Err(anyhow!("No pyproject.toml in {}", cwd));
+        }
+        Ok(())
+    }
+}
+
+/// `pixi run [-e <env>]`; without a name pixi uses the default environment.
+pub struct Pixi;
+
+impl EnvironmentBackend for Pixi {
+    fn build_command(&self, task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        let mut args = vec!["run".to_string()];
+        if let Some(env) = task.env_name.as_ref().filter(|e| !e.is_empty()) {
+            args.push("-e".to_string());
+            args.push(env.clone());
+        }
+        args.extend(argv);
+        Ok(Launch::new("pixi", args))
+    }
+
+    fn validate(&self, env_name: Option<&str>, cwd: &str) -> Result<()> {
+        let cwd = Path::new(cwd);
+        if !cwd.join("pixi.toml").is_file() && !cwd.join("pyproject.toml").is_file() {
+            return Err(anyhow!("No pixi.toml or pyproject.toml in {}", cwd.display()));
+        }
+        if let Some(name) = env_name {
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(anyhow!("Invalid pixi environment name {}", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `docker run`, or any runtime with the same CLI (podman, nerdctl).
+/// `env_name` is the image.
+///
+/// The task's cwd is mounted at the same path and used as the working
+/// directory, so relative paths behave as they would outside. The container
+/// gets a TTY on the task's PTY and is removed when it exits.
+pub struct Container {
+    pub runtime: String,
+}
+
+impl Container {
+    /// Unique per run, so a container left over from a lost run can't block
+    /// the next one, and worked out again after a restart from the task's
+    /// `last_run_id`.
+    fn name(task: &Task) -> String {
+        match &task.last_run_id {
+            Some(run_id) => format!("task-mgr-{}-{}", task.id, run_id),
+            None => format!("task-mgr-{}", task.id),
+        }
+    }
+}
+
+impl EnvironmentBackend for Container {
+    fn build_command(&self, task: &Task, argv: Vec<String>, env: &[String]) -> Result<Launch> {
+        let image = task.env_name.as_ref().ok_or_else(|| anyhow!("Image required for containers"))?;
+        let cwd = Path::new(&task.cwd).canonicalize()
+            .with_context(|| format!("Invalid working directory {}", task.cwd))?;
+        let cwd = cwd.to_string_lossy();
+
+        let mut args: Vec<String> = ["run", "--rm", "-i", "-t", "--name"].map(String::from).into();
+        args.push(Self::name(task));
+        args.extend(["-v".to_string(), format!("{}:{}", cwd, cwd), "-w".to_string(), cwd.to_string()]);
+        // Name only: the runtime takes the value from its own environment,
+        // which keeps secrets off the command line.
+        for name in env {
+            args.extend(["-e".to_string(), name.clone()]);
+        }
+
+        let options = task.container.as_ref().map(|c| c.0.clone()).unwrap_or_default();
+        if let Some(cpus) = options.cpus {
+            args.extend(["--cpus".to_string(), cpus.to_string()]);
+        }
+        if let Some(memory) = options.memory_mb {
+            args.extend(["--memory".to_string(), format!("{}m", memory)]);
+        }
+        if let Some(gpus) = options.gpus {
+            args.extend(["--gpus".to_string(), gpus]);
+        }
+        if let Some(network) = options.network {
+            args.extend(["--network".to_string(), network]);
+        }
+        args.extend(options.extra_args);
+
+        args.push(image.clone());
+        args.extend(argv);
+        Ok(Launch::new(&self.runtime, args))
+    }
+
+    /// With a TTY the client doesn't forward signals, and killing it would
+    /// leave the container running, so signals go to the container itself.
+    fn signal_command(&self, task: &Task) -> Option<Vec<String>> {
+        Some(vec![self.runtime.clone(), "kill".to_string(), "--signal".to_string(), "{signal}".to_string(), Self::name(task)])
+    }
+
+    fn validate(&self, env_name: Option<&str>, cwd: &str) -> Result<()> {
+        env_name.ok_or_else(|| anyhow!("Image required for containers"))?;
+        if !Path::new(cwd).is_dir() {
+            return Err(anyhow!("Working directory {} not found", cwd));
+        }
+        Ok(())
+    }
+
+    /// Images already pulled to this machine.
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        let output = match Command::new(&self.runtime).args(["images", "--format", "{{.Repository}}:{{.Tag}}"]).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", self.runtime)),
+        };
+        if !output.status.success() {
+            return Err(anyhow!("{} images failed: {}", self.runtime, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|image| !image.is_empty() && !image.contains("<none>"))
+            .map(|image| DiscoveredEnv {
+                env_type: "container".to_string(),
+                name: image.to_string(),
+                path: None,
+                display_name: None,
+                language: None,
+                metadata: None,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/u".into()),
+            "PATH" => Some("/bin".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitutes_like_safe_substitute() {
+        let cases = [
+            ("$HOME/lib", "/home/u/lib"),
+            ("${HOME}lib", "/home/ulib"),
+            ("$PATH:$HOME", "/bin:/home/u"),
+            ("x${EMPTY}y", "xy"),
+            ("$$HOME", "$HOME"),
+            ("cost: $$5", "cost: $5"),
+            ("$MISSING and ${MISSING}", "$MISSING and ${MISSING}"),
+            ("$1 ${1x} ${HOME", "$1 ${1x} ${HOME"),
+            ("trailing $", "trailing $"),
+            ("${}", "${}"),
+            ("no vars", "no vars"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(substitute(value, lookup), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn substituted_text_is_not_expanded_again() {
+        let lookup = |name: &str| (name == "A").then(|| "$B".to_string());
+        assert_eq!(substitute("$A", lookup), "$B");
+    }
+
+    /// A directory of its own under the system temp dir, emptied first.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("task-mgr-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    /// A stand-in for docker that records its arguments, one per line, and
+    /// answers `images` with a fixed list.
+    fn fake_runtime(dir: &Path) -> Container {
+        use std::os::unix::fs::PermissionsExt;
+        let runtime = dir.join("docker");
+        let script = format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > {}/args\n[ \"$1\" = images ] && printf 'alpine:3.20\\n<none>:<none>\\npython:3.12\\n'\nexit 0\n",
+            dir.display(),
+        );
+        fs::write(&runtime, script).unwrap();
+        fs::set_permissions(&runtime, fs::Permissions::from_mode(0o755)).unwrap();
+        Container { runtime: runtime.to_string_lossy().into_owned() }
+    }
+
+    fn container_task(cwd: &Path) -> Task {
+        Task {
+            id: "t1".into(),
+            env_type: "container".into(),
+            env_name: Some("alpine:3.20".into()),
+            cwd: cwd.to_string_lossy().into_owned(),
+            last_run_id: Some("r1".into()),
+            ..crate::core::models::test_task()
+        }
+    }
+
+    #[test]
+    fn container_run_arguments() {
+        let dir = scratch("container-run");
+        let backend = fake_runtime(&dir);
+        let cwd = dir.display().to_string();
+        let mut task = container_task(&dir);
+        task.container = Some(sqlx::types::Json(crate::core::models::ContainerOptions {
+            cpus: Some(1.5),
+            memory_mb: Some(512),
+            gpus: Some("device=0".into()),
+            network: None,
+            extra_args: vec!["--shm-size".into(), "2g".into()],
+        }));
+
+        let launch = backend.build_command(&task, vec!["python".into(), "a b.py".into()], &["API_KEY".into(), "HOME".into()]).unwrap();
+        assert_eq!(launch.program, backend.runtime);
+        let mount = format!("{}:{}", cwd, cwd);
+        let expected = [
+            "run", "--rm", "-i", "-t", "--name", "task-mgr-t1-r1",
+            "-v", &mount, "-w", &cwd,
+            "-e", "API_KEY", "-e", "HOME",
+            "--cpus", "1.5", "--memory", "512m", "--gpus", "device=0",
+            "--shm-size", "2g",
+            "alpine:3.20", "python", "a b.py",
+        ];
+        assert_eq!(launch.args, expected);
+        // Values stay off the command line.
+        assert!(launch.env.is_empty());
+    }
+
+    #[test]
+    fn container_needs_an_image_and_a_cwd() {
+        let dir = scratch("container-validate");
+        let backend = fake_runtime(&dir);
+        let cwd = dir.to_string_lossy();
+        assert!(backend.validate(Some("alpine"), &cwd).is_ok());
+        assert!(backend.validate(None, &cwd).is_err());
+        assert!(backend.validate(Some("alpine"), &dir.join("missing").to_string_lossy()).is_err());
+        let task = Task { env_name: None, ..container_task(&dir) };
+        assert!(backend.build_command(&task, vec!["true".into()], &[]).is_err());
+    }
+
+    #[test]
+    fn container_signals_go_to_this_runs_container() {
+        let dir = scratch("container-signal");
+        let backend = fake_runtime(&dir);
+        let command = backend.signal_command(&container_task(&dir)).unwrap();
+        tokio::runtime::Runtime::new().unwrap()
+            .block_on(crate::exec::signals::run_signal_command(&command, libc::SIGTERM))
+            .unwrap();
+        let args = fs::read_to_string(dir.join("args")).unwrap();
+        assert_eq!(args.lines().collect::<Vec<_>>(), ["kill", "--signal", "SIGTERM", "task-mgr-t1-r1"]);
+
+        // Another run of the same task gets a container of its own.
+        let next = Task { last_run_id: Some("r2".into()), ..container_task(&dir) };
+        assert_ne!(backend.signal_command(&next), Some(command));
+    }
+
+    #[test]
+    fn container_discovers_tagged_images() {
+        let dir = scratch("container-discover");
+        let names: Vec<String> = fake_runtime(&dir).discover().unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["alpine:3.20", "python:3.12"]);
+        let missing = Container { runtime: dir.join("nope").to_string_lossy().into_owned() };
+        assert!(missing.discover().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::core::models::{CommandMode, DiscoveredEnv, Task};
+use anyhow::{Result, anyhow, Context};
+use serde::Deserialize;
+use std::fs;
+
+pub mod builtin;
+pub mod template;
+
+/// What a backend runs for a task: the command line, and any variables the
+/// environment needs. Those are applied before the task's own.
+pub struct Launch {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl Launch {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        Self { program: program.into(), args, env: vec![] }
+    }
+
+    /// Runs `argv` as it is.
+    pub fn argv(mut argv: Vec<String>) -> Self {
+        let program = argv.remove(0);
+        Self::new(program, argv)
+    }
+}
+
+/// How tasks of one `env_type` are run.
+///
+/// Backends only wrap: the task's own argv (see `inner_argv`) is worked out
+/// once, the same way for every backend.
+pub trait EnvironmentBackend: Send + Sync {
+    /// How to run `argv` inside the environment. Called once per spawn, so
+    /// anything read from disk here is read once.
+    ///
+    /// `env` names the variables the task sets for itself. They are set on
+    /// the spawned process; a wrapper that doesn't pass its environment on
+    /// (a container) has to forward them.
+    fn build_command(&self, task: &Task, argv: Vec<String>, env: &[String]) -> Result<Launch>;
+
+    /// For environments where the task doesn't run in the spawned process
+    /// group: the command that delivers a signal to it, with `{signal}` for
+    /// the signal's name. Signals go to the process group otherwise.
+    fn signal_command(&self, _task: &Task) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Check a new task's `env_name` (never empty) and cwd, so a typo shows
+    /// up at creation rather than when the task is first started.
+    fn validate(&self, _env_name: Option<&str>, _cwd: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Environments of this kind available on this machine. May block.
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        Ok(vec![])
+    }
+}
+
+/// The backends known to this server, by `env_type`.
+pub struct EnvRegistry {
+    backends: BTreeMap<String, Arc<dyn EnvironmentBackend>>,
+    /// Last discovery results; discovery shells out and walks directories,
+    /// so it only runs again when asked to.
+    discovered: Mutex<Option<Vec<DiscoveredEnv>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BackendsFile {
+    #[serde(default)]
+    backends: BTreeMap<String, template::TemplateConfig>,
+}
+
+impl EnvRegistry {
+    /// `search_roots` are where venvs and uv projects are looked for;
+    /// `container_runtime` is the docker-compatible CLI containers run with.
+    pub fn builtin(search_roots: &[PathBuf], container_runtime: &str) -> Self {
+        let mut backends: BTreeMap<String, Arc<dyn EnvironmentBackend>> = BTreeMap::new();
+        backends.insert("shell".into(), Arc::new(builtin::Shell));
+        for binary in ["conda", "mamba", "micromamba"] {
+            backends.insert(binary.into(), Arc::new(builtin::Conda { binary }));
+        }
+        backends.insert("uv".into(), Arc::new(builtin::Uv { roots: search_roots.to_vec() }));
+        backends.insert("jupyter".into(), Arc::new(builtin::Jupyter));
+        backends.insert("venv".into(), Arc::new(builtin::Venv { roots: search_roots.to_vec() }));
+        backends.insert("poetry".into(), Arc::new(builtin::Poetry));
+        backends.insert("pixi".into(), Arc::new(builtin::Pixi));
+        backends.insert("container".into(), Arc::new(builtin::Container { runtime: container_runtime.to_string() }));
+        Self { backends, discovered: Mutex::new(None) }
+    }
+
+    /// Adds the template backends defined in a TOML file (see
+    /// `template::TemplateConfig`). A missing file defines none.
+    pub fn load_file(mut self, path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(self),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let file: BackendsFile = toml::from_str(&content)
+            .with_context(|| format!("Invalid backends file {}", path.display()))?;
+
+        for (name, config) in file.backends {
+            if self.backends.contains_key(&name) {
+                return Err(anyhow!("Backend {} in {} clashes with a built-in one", name, path.display()));
+            }
+            let backend = template::TemplateBackend::new(&name, config)?;
+            tracing::info!("Loaded environment backend {}", name);
+            self.backends.insert(name, Arc::new(backend));
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, env_type: &str) -> Result<&dyn EnvironmentBackend> {
+        self.backends.get(env_type)
+            .map(|b| b.as_ref())
+            .ok_or_else(|| anyhow!("Unknown environment type {}", env_type))
+    }
+
+    pub fn env_types(&self) -> Vec<String> {
+        self.backends.keys().cloned().collect()
+    }
+
+    pub fn validate(&self, env_type: &str, env_name: Option<&str>, cwd: &str) -> Result<()> {
+        self.get(env_type)?.validate(env_name.filter(|n| !n.is_empty()), cwd)
+    }
+
+    /// Everything every backend can find, from the cache unless `refresh`
+    /// is set or nothing has been discovered yet. One failing backend
+    /// doesn't hide the others' results. Blocks while discovery runs.
+    pub fn discover(&self, refresh: bool) -> Vec<DiscoveredEnv> {
+        if !refresh {
+            if let Some(found) = self.discovered.lock().unwrap().as_ref() {
+                return found.clone();
+            }
+        }
+
+        let mut found = Vec::new();
+        for (env_type, backend) in &self.backends {
+            match backend.discover() {
+                Ok(envs) => found.extend(envs),
+                Err(e) => tracing::warn!("Discovering {} environments failed: {:?}", env_type, e),
+            }
+        }
+        *self.discovered.lock().unwrap() = Some(found.clone());
+        found
+    }
+
+    /// The command for a task, with everything its environment adds on top
+    /// of the inherited one in order of increasing precedence: what the
+    /// backend needs (a kernel's `env`, a venv's activation), then the
+    /// task's `env_file`, then its `env` map, then `overrides` (secrets and
+    /// anything assigned at spawn). Later entries override earlier ones, and
+    /// all of them override inherited variables.
+    pub fn launch(&self, task: &Task, overrides: &[(String, String)]) -> Result<Launch> {
+        let mut vars = Vec::new();
+        if let Some(env_file) = &task.env_file {
+            let path = Path::new(&task.cwd).join(env_file);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read env file {}: {}", path.display(), e))?;
+            vars.extend(parse_env_file(&content)?);
+        }
+        vars.extend(task.env.0.iter().map(|(k, v)| (k.clone(), v.clone())));
+        vars.extend(overrides.iter().cloned());
+
+        let mut names: Vec<String> = vars.iter().map(|(k, _)| k.clone()).collect();
+        names.sort();
+        names.dedup();
+        let mut launch = self.get(&task.env_type)?.build_command(task, inner_argv(task)?, &names)?;
+        launch.env.extend(vars);
+        Ok(launch)
+    }
+
+    pub fn signal_command(&self, task: &Task) -> Option<Vec<String>> {
+        self.get(&task.env_type).ok()?.signal_command(task)
+    }
+}
+
+/// The argv to run inside the environment, before any env wrapper.
+fn inner_argv(task: &Task) -> Result<Vec<String>> {
+    let args: Vec<String> = serde_json::from_str(&task.args)
+        .map_err(|e| anyhow!("Invalid args: {}", e))?;
+    match task.mode {
+        CommandMode::Shell => {
+            // sh -c so pipes and redirects work. Any args become the
+            // script's positional parameters, with "sh" as $0.
+            let mut argv = vec!["sh".to_string(), "-c".to_string(), task.command.clone()];
+            if !args.is_empty() {
+                argv.push("sh".to_string());
+                argv.extend(args);
+            }
+            Ok(argv)
+        }
+        CommandMode::Exec => {
+            if task.command.is_empty() {
+                return Err(anyhow!("Program required in exec mode"));
+            }
+            let mut argv = vec![task.command.clone()];
+            argv.extend(args);
+            Ok(argv)
+        }
+        CommandMode::Notebook => {
+            // Any args go to nbconvert, e.g. a per-cell timeout.
+            let mut argv: Vec<String> = ["jupyter", "nbconvert", "--to", "notebook", "--execute", "--output"]
+                .map(String::from)
+                .into();
+            argv.push(notebook_output(&task.command)?);
+            argv.push(task.command.clone());
+            argv.extend(args);
+            Ok(argv)
+        }
+    }
+}
+
+/// File name for an executed copy of `notebook`, next to it and stamped
+/// with the time, so every run keeps its own outputs and the original is
+/// never overwritten.
+fn notebook_output(notebook: &str) -> Result<String> {
+    let path = Path::new(notebook);
+    if path.extension().is_none_or(|e| e != "ipynb") {
+        return Err(anyhow!("Notebook mode needs an .ipynb file, got {}", notebook));
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    Ok(format!("{}.{}.ipynb", stem, chrono::Utc::now().format("%Y%m%d-%H%M%S")))
+}
+
+/// Parses the common `.env` format: `KEY=value` lines, optionally prefixed
+/// with `export`, with `#` comments and blank lines ignored. Values may be
+/// single-quoted (taken literally) or double-quoted, where `\n`, `\"` and
+/// `\\` are escapes and any other backslash is kept as written. A `#` after
+/// whitespace or a closing quote starts a comment; inside an unquoted word,
+/// as in `a#b`, it is part of the value. There is no `$VAR` expansion.
+fn parse_env_file(content: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| anyhow!("Line {} of env file is not KEY=value", i + 1))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(anyhow!("Invalid variable name on line {} of env file", i + 1));
+        }
+        vars.push((key.to_string(), unquote(value.trim())));
+    }
+    Ok(vars)
+}
+
+fn unquote(value: &str) -> String {
+    if let Some((inner, rest)) = split_quoted(value) {
+        if rest.is_empty() || rest.starts_with('#') {
+            return inner;
+        }
+    }
+    // Unquoted values may carry a trailing comment.
+    let comment = value.char_indices()
+        .find(|&(i, c)| c == '#' && value[..i].ends_with(char::is_whitespace));
+    match comment {
+        Some((i, _)) => value[..i].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// The contents of the quoted string `value` starts with, and what follows
+/// its closing quote. `None` if it doesn't start with a quote or the quote
+/// is never closed.
+fn split_quoted(value: &str) -> Option<(String, &str)> {
+    let mut chars = value.char_indices();
+    let (_, quote) = chars.next().filter(|(_, c)| matches!(c, '\'' | '"'))?;
+    let mut out = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Some((out, value[i + 1..].trim_start())),
+            '\\' if quote == '"' => match chars.clone().next() {
+                Some((_, 'n')) => { out.push('\n'); chars.next(); }
+                Some((_, e @ ('"' | '\\'))) => { out.push(e); chars.next(); }
+                _ => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Vec<(String, String)> {
+        parse_env_file(content).unwrap()
+    }
+
+    #[test]
+    fn env_file_lines() {
+        let cases = [
+            ("A=1", ("A", "1")),
+            ("export A=1", ("A", "1")),
+            ("  A = 1  ", ("A", "1")),
+            ("A=", ("A", "")),
+            ("URL=postgres://u:p@h/db?x=1", ("URL", "postgres://u:p@h/db?x=1")),
+            ("A=1 # note", ("A", "1")),
+            ("A=1\t# tab before the comment", ("A", "1")),
+            ("A=a#b", ("A", "a#b")),
+            ("A='a b # c'", ("A", "a b # c")),
+            ("A='x' # note", ("A", "x")),
+            ("A=\"x\"# note", ("A", "x")),
+            ("A='lit\\n'", ("A", "lit\\n")),
+            ("A=\"one\\ntwo\"", ("A", "one\ntwo")),
+            ("A=\"q\\\"q \\\\ \\t\"", ("A", "q\"q \\ \\t")),
+            ("A=\"unclosed", ("A", "\"unclosed")),
+            ("A=\"x\" y", ("A", "\"x\" y")),
+        ];
+        for (line, (key, value)) in cases {
+            assert_eq!(parse(line), [(key.to_string(), value.to_string())], "{}", line);
+        }
+    }
+
+    #[test]
+    fn skips_blanks_and_comments() {
+        let vars = parse("# header\n\n   \nA=1\n  # indented\nB=2\n");
+        assert_eq!(vars, [("A".to_string(), "1".to_string()), ("B".to_string(), "2".to_string())]);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        for content in ["A", "=1", "MY VAR=1", "export =1", "A=1\nnot a var"] {
+            assert!(parse_env_file(content).is_err(), "{}", content);
+        }
+        let err = parse_env_file("A=1\n\nB C=2").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use anyhow::{Result, anyhow, Context};
+use serde::Deserialize;
+use crate::core::models::{CommandMode, DiscoveredEnv, Task};
+use super::{EnvironmentBackend, Launch};
+
+/// A site-specific backend defined in the backends file:
+///
+/// ```toml
+/// [backends.nix]
+/// command = "nix develop {env} -c sh -c {command}"
+/// env_required = true
+/// discover = "ls /opt/flakes"   # optional, one env name per line
+///
+/// [backends.nix.env]            # optional extra variables
+/// NIX_CONFIG = "warn-dirty = false"
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct TemplateConfig {
+    pub command: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub env_required: bool,
+    pub discover: Option<String>,
+}
+
+/// Runs tasks through a command template. The template is split into words
+/// like a shell would, then placeholders are filled in per word, so a
+/// substituted value never splits into several arguments:
+///
+/// - `{argv}`, as a whole word, expands to the task's argv, one word each
+/// - `{command}` is the task as a single shell command string
+/// - `{env}` is the task's `env_name`, `{cwd}` its working directory; both must
+///   stick to path-like characters, since they may land inside a shell string
+pub struct TemplateBackend {
+    name: String,
+    words: Vec<String>,
+    config: TemplateConfig,
+}
+
+impl TemplateBackend {
+    pub fn new(name: &str, config: TemplateConfig) -> Result<Self> {
+        let words = shlex::split(&config.command)
+            .filter(|w| !w.is_empty())
+            .ok_or_else(|| anyhow!("Backend {}: command is empty or badly quoted", name))?;
+        if !words.iter().any(|w| w == "{argv}" || w.contains("{command}")) {
+            return Err(anyhow!("Backend {}: command must use {{argv}} or {{command}}", name));
+        }
+        Ok(Self { name: name.to_string(), words, config })
+    }
+
+    fn uses_cwd(&self) -> bool {
+        self.words.iter().any(|w| w.contains("{cwd}"))
+    }
+}
+
+/// The argv as one string for `sh -c`. A plain shell-mode task is already
+/// a script, so it goes in as written rather than wrapped in another shell.
+fn shell_command(task: &Task, argv: &[String]) -> Result<String> {
+    if task.mode == CommandMode::Shell && argv.len() == 3 {
+        return Ok(argv[2].clone());
+    }
+    shlex::try_join(argv.iter().map(String::as_str)).map_err(|e| anyhow!("Can't quote command: {}", e))
+}
+
+/// Checks a value bound for `{env}` or `{cwd}`. The template author decides
+/// where those land, maybe inside an `sh -c` string or where an option could
+/// go, so only a plain path-like character set is allowed and a leading `-`
+/// is refused.
+fn check_word(what: &str, value: &str) -> Result<()> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "._/#:@+-".contains(c);
+    if value.starts_with('-') || !value.chars().all(safe) {
+        return Err(anyhow!("Invalid {} {:?}: only letters, digits and ._/#:@+- are allowed, and no leading '-'", what, value));
+    }
+    Ok(())
+}
+
+/// Replaces `{name}` placeholders in one pass, so substituted text is never
+/// expanded again. Unknown names are left as they are.
+fn expand(word: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut rest = word;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        match vars.iter().find(|(name, _)| tail[1..].starts_with(name) && tail[1 + name.len()..].starts_with('}')) {
+            Some((name, value)) => {
+                out.push_str(value);
+                rest = &tail[name.len() + 2..];
+            }
+            None => {
+                out.push('{');
+                rest = &tail[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+impl EnvironmentBackend for TemplateBackend {
+    fn build_command(&self, task: &Task, argv: Vec<String>, _env: &[String]) -> Result<Launch> {
+        let env = task.env_name.as_deref().unwrap_or("");
+        if self.config.env_required && env.is_empty() {
+            return Err(anyhow!("Environment name required for {}", self.name));
+        }
+        check_word("environment name", env)?;
+        if self.uses_cwd() {
+            check_word("working directory", &task.cwd)?;
+        }
+        let command = shell_command(task, &argv)?;
+        let vars = [("env", env), ("cwd", task.cwd.as_str()), ("command", command.as_str())];
+
+        let mut words = Vec::new();
+        for word in &self.words {
+            if word == "{argv}" {
+                words.extend(argv.iter().cloned());
+            } else {
+                words.push(expand(word, &vars));
+            }
+        }
+        let mut launch = Launch::argv(words);
+        launch.env = self.config.env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        Ok(launch)
+    }
+
+    fn validate(&self, env_name: Option<&str>, cwd: &str) -> Result<()> {
+        if self.config.env_required && env_name.is_none() {
+            return Err(anyhow!("Environment name required for {}", self.name));
+        }
+        if let Some(env) = env_name {
+            check_word("environment name", env)?;
+        }
+        if self.uses_cwd() {
+            check_word("working directory", cwd)?;
+        }
+        Ok(())
+    }
+
+    fn discover(&self) -> Result<Vec<DiscoveredEnv>> {
+        let Some(script) = &self.config.discover else { return Ok(vec![]) };
+        let output = Command::new("sh").arg("-c").arg(script).output()
+            .with_context(|| format!("Failed to run discover command for {}", self.name))?;
+        if !output.status.success() {
+            return Err(anyhow!("Discover command for {} failed: {}", self.name, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|name| DiscoveredEnv {
+                env_type: self.name.clone(),
+                name: name.to_string(),
+                path: None,
+                display_name: None,
+                language: None,
+                metadata: None,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::models::test_task;
+    use super::*;
+
+    fn backend(command: &str, env_required: bool) -> TemplateBackend {
+        let config = TemplateConfig { command: command.into(), env: BTreeMap::new(), env_required, discover: None };
+        TemplateBackend::new("nix", config).unwrap()
+    }
+
+    fn task(env_name: Option<&str>) -> Task {
+        Task { env_name: env_name.map(String::from), cwd: "/work".into(), ..test_task() }
+    }
+
+    fn argv(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn expands_placeholders_once() {
+        let vars = [("env", "{cwd}"), ("cwd", "/w")];
+        assert_eq!(expand("{env}", &vars), "{cwd}");
+        assert_eq!(expand("--dir={cwd}/x", &vars), "--dir=/w/x");
+        assert_eq!(expand("{cwd}{cwd}", &vars), "/w/w");
+        assert_eq!(expand("{other} {cwd", &vars), "{other} {cwd");
+        assert_eq!(expand("{{cwd}}", &vars), "{/w}");
+        assert_eq!(expand("", &vars), "");
+    }
+
+    #[test]
+    fn argv_and_command_placement() {
+        let t = Task { mode: CommandMode::Exec, ..task(Some("dev")) };
+        let launch = backend("nix develop {env} -c {argv}", false)
+            .build_command(&t, argv(&["python", "a b.py"]), &[]).unwrap();
+        assert_eq!(launch.program, "nix");
+        assert_eq!(launch.args, ["develop", "dev", "-c", "python", "a b.py"]);
+
+        let launch = backend("run --in {cwd} -- sh -c {command}", false)
+            .build_command(&t, argv(&["python", "a b.py"]), &[]).unwrap();
+        assert_eq!(launch.args, ["--in", "/work", "--", "sh", "-c", "python 'a b.py'"]);
+    }
+
+    #[test]
+    fn shell_tasks_keep_their_script() {
+        let launch = backend("wrap {command}", false)
+            .build_command(&task(None), argv(&["sh", "-c", "echo $HOME | wc"]), &[]).unwrap();
+        assert_eq!(launch.args, ["echo $HOME | wc"]);
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let config = |command: &str| TemplateConfig { command: command.into(), env: BTreeMap::new(), env_required: false, discover: None };
+        assert!(TemplateBackend::new("x", config("")).is_err());
+        assert!(TemplateBackend::new("x", config("run 'unclosed")).is_err());
+        assert!(TemplateBackend::new("x", config("run {env}")).is_err());
+    }
+
+    #[test]
+    fn env_names() {
+        let b = backend("nix develop {env} -c {argv}", true);
+        assert!(b.validate(Some("dev"), "/").is_ok());
+        assert!(b.validate(Some("./flakes#py311"), "/").is_ok());
+        assert!(b.validate(None, "/").is_err());
+        for bad in ["my env", "dev\n", "-c", "--impure", "x;reboot", "$(id)", "`id`", "a|b", "a'b", "a&&b", "${HOME}"] {
+            assert!(b.validate(Some(bad), "/").is_err(), "{:?}", bad);
+            assert!(b.build_command(&task(Some(bad)), argv(&["true"]), &[]).is_err(), "{:?}", bad);
+        }
+        assert!(b.build_command(&task(None), argv(&["true"]), &[]).is_err());
+    }
+
+    #[test]
+    fn cwd_is_checked_only_when_used() {
+        let b = backend("run --in {cwd} -- sh -c {command}", false);
+        assert!(b.validate(None, "/srv/app-1.2").is_ok());
+        for bad in ["/tmp/$(id)", "/tmp/a;reboot", "/tmp/`id`", "/my dir", "-x"] {
+            assert!(b.validate(None, bad).is_err(), "{:?}", bad);
+            let t = Task { cwd: bad.into(), ..task(None) };
+            assert!(b.build_command(&t, argv(&["true"]), &[]).is_err(), "{:?}", bad);
+        }
+        let b = backend("wrap {command}", false);
+        assert!(b.validate(None, "/my dir").is_ok());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use anyhow::{Result, anyhow, Context};
+use serde::Deserialize;
+use crate::monitor::{LatestMetrics, MetricsSource};
+
+/// One GPU as the allocator sees it. Memory is in MiB, as nvidia-smi
+/// reports it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GpuDevice {
+    pub index: u32,
+    #[serde(default)]
+    pub mem_used: u64,
+    pub mem_total: u64,
+    #[serde(default)]
+    pub util: u32,
+}
+
+/// Where the allocator learns which GPUs there are and how busy they are.
+pub trait GpuInventory: Send + Sync {
+    /// `None` while nothing is known yet, which is not the same as a
+    /// machine without GPUs.
+    fn devices(&self) -> Option<Vec<GpuDevice>>;
+}
+
+impl GpuInventory for LatestMetrics {
+    fn devices(&self) -> Option<Vec<GpuDevice>> {
+        let metrics = self.latest()?;
+        Some(metrics.gpus.into_iter().map(|g| GpuDevice {
+            index: g.index,
+            mem_used: g.mem_used,
+            mem_total: g.mem_total,
+            util: g.util,
+        }).collect())
+    }
+}
+
+/// A fixed device list, such as a fixture on a machine with no GPU:
+///
+/// ```json
+/// [{"index": 0, "mem_total": 81920}, {"index": 1, "mem_total": 81920, "mem_used": 4096}]
+/// ```
+#[derive(Default)]
+pub struct StaticInventory {
+    devices: Vec<GpuDevice>,
+}
+
+impl StaticInventory {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read GPU inventory {}", path.display()))?;
+        let devices = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid GPU inventory {}", path.display()))?;
+        Ok(Self { devices })
+    }
+}
+
+impl GpuInventory for StaticInventory {
+    fn devices(&self) -> Option<Vec<GpuDevice>> {
+        Some(self.devices.clone())
+    }
+}
+
+/// Hands out whole GPUs. A device goes to one run at a time, and is free
+/// again once that run's `GpuReservation` is dropped.
+pub struct GpuAllocator {
+    inventory: Arc<dyn GpuInventory>,
+    taken: Mutex<HashSet<u32>>,
+}
+
+/// Devices held by one run.
+pub struct GpuReservation {
+    allocator: Arc<GpuAllocator>,
+    devices: Vec<u32>,
+}
+
+impl GpuReservation {
+    /// The value for `CUDA_VISIBLE_DEVICES`, also how the devices are stored.
+    pub fn visible_devices(&self) -> String {
+        self.devices.iter().map(u32::to_string).collect::<Vec<_>>().join(",")
+    }
+}
+
+impl Drop for GpuReservation {
+    fn drop(&mut self) {
+        let mut taken = self.allocator.taken.lock().unwrap();
+        for d in &self.devices {
+            taken.remove(d);
+        }
+    }
+}
+
+/// The `count` least loaded devices outside `taken` with at least
+/// `min_free_mb` unused memory each, in index order.
+fn pick(devices: &[GpuDevice], taken: &HashSet<u32>, count: u32, min_free_mb: Option<i64>) -> Option<Vec<u32>> {
+    let mut free: Vec<&GpuDevice> = devices.iter()
+        .filter(|d| !taken.contains(&d.index))
+        .filter(|d| min_free_mb.is_none_or(|need| d.mem_total.saturating_sub(d.mem_used) as i64 >= need))
+        .collect();
+    if free.len() < count as usize {
+        return None;
+    }
+    free.sort_by_key(|d| (d.mem_used, d.util, d.index));
+    let mut picked: Vec<u32> = free[..count as usize].iter().map(|d| d.index).collect();
+    picked.sort();
+    Some(picked)
+}
+
+impl GpuAllocator {
+    pub fn new(inventory: Arc<dyn GpuInventory>) -> Self {
+        Self { inventory, taken: Mutex::new(HashSet::new()) }
+    }
+
+    /// Whether `reserve` would succeed right now.
+    pub fn available(&self, count: u32, min_free_mb: Option<i64>) -> bool {
+        let Some(devices) = self.inventory.devices() else { return false };
+        pick(&devices, &self.taken.lock().unwrap(), count, min_free_mb).is_some()
+    }
+
+    pub fn reserve(self: &Arc<Self>, count: u32, min_free_mb: Option<i64>) -> Result<GpuReservation> {
+        let devices = self.inventory.devices().ok_or_else(|| anyhow!("No GPU information yet"))?;
+        let mut taken = self.taken.lock().unwrap();
+        let picked = pick(&devices, &taken, count, min_free_mb)
+            .ok_or_else(|| anyhow!("Not enough free GPUs: need {}", count))?;
+        taken.extend(&picked);
+        Ok(GpuReservation { allocator: self.clone(), devices: picked })
+    }
+
+    /// Take back devices recorded as `visible_devices`, for a run that
+    /// outlived a restart.
+    pub fn claim(self: &Arc<Self>, visible_devices: &str) -> GpuReservation {
+        let devices: Vec<u32> = visible_devices.split(',').filter_map(|d| d.trim().parse().ok()).collect();
+        self.taken.lock().unwrap().extend(&devices);
+        GpuReservation { allocator: self.clone(), devices }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use super::*;
+
+    fn device(index: u32, mem_used: u64, util: u32) -> GpuDevice {
+        GpuDevice { index, mem_used, mem_total: 16384, util }
+    }
+
+    /// Four 16 GB devices; 1 is the busiest, 3 the idlest.
+    fn allocator() -> Arc<GpuAllocator> {
+        let devices = vec![device(0, 2048, 10), device(1, 12288, 90), device(2, 2048, 5), device(3, 0, 0)];
+        Arc::new(GpuAllocator::new(Arc::new(StaticInventory { devices })))
+    }
+
+    #[test]
+    fn reserves_least_loaded_and_releases_on_drop() {
+        let gpus = allocator();
+        let first = gpus.reserve(2, None).unwrap();
+        assert_eq!(first.visible_devices(), "2,3");
+        let second = gpus.reserve(2, None).unwrap();
+        assert_eq!(second.visible_devices(), "0,1");
+        assert!(!gpus.available(1, None));
+
+        drop(first);
+        assert!(gpus.available(2, None));
+        assert!(!gpus.available(3, None));
+        assert_eq!(gpus.reserve(2, None).unwrap().visible_devices(), "2,3");
+    }
+
+    #[test]
+    fn too_few_devices() {
+        let gpus = allocator();
+        assert!(!gpus.available(5, None));
+        assert!(gpus.reserve(5, None).is_err());
+        let _held = gpus.reserve(3, None).unwrap();
+        assert!(gpus.reserve(2, None).is_err());
+        assert!(gpus.reserve(1, None).is_ok());
+    }
+
+    #[test]
+    fn min_free_memory() {
+        let gpus = allocator();
+        // Device 1 has 4 GB free, the others 14 GB or more.
+        assert!(gpus.available(4, Some(4096)));
+        assert!(!gpus.available(4, Some(4097)));
+        assert_eq!(gpus.reserve(1, Some(16384)).unwrap().visible_devices(), "3");
+        assert!(gpus.reserve(1, Some(20000)).is_err());
+    }
+
+    #[test]
+    fn nothing_known_yet() {
+        struct Unknown;
+        impl GpuInventory for Unknown {
+            fn devices(&self) -> Option<Vec<GpuDevice>> {
+                None
+            }
+        }
+        let gpus = Arc::new(GpuAllocator::new(Arc::new(Unknown)));
+        assert!(!gpus.available(1, None));
+        assert!(gpus.reserve(1, None).is_err());
+    }
+
+    #[test]
+    fn claims_recorded_devices() {
+        let gpus = allocator();
+        let claimed = gpus.claim("3, 2");
+        assert_eq!(gpus.reserve(2, None).unwrap().visible_devices(), "0,1");
+        drop(claimed);
+        assert!(gpus.available(4, None));
+    }
+
+    #[test]
+    fn concurrent_reservations_never_share() {
+        let gpus = allocator();
+        let start = Arc::new(Barrier::new(8));
+        let held: Vec<_> = (0..8).map(|_| {
+            let (gpus, start) = (gpus.clone(), start.clone());
+            std::thread::spawn(move || {
+                start.wait();
+                gpus.reserve(1, None).ok()
+            })
+        }).collect();
+        let held: Vec<GpuReservation> = held.into_iter().filter_map(|t| t.join().unwrap()).collect();
+
+        let mut devices: Vec<u32> = held.iter().flat_map(|r| r.devices.clone()).collect();
+        devices.sort();
+        assert_eq!(devices, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,306 @@
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Result, anyhow, Context};
+use serde::{Deserialize, Serialize};
+use crate::core::models::{IoClass, ResourceLimits};
+
+/// First argument of the server binary when it is acting as a launcher:
+/// `server --launch-with-limits <plan> <program> [args...]`.
+///
+/// Limits have to be in place before the task's own code runs, and
+/// portable-pty gives no hook between fork and exec, so limited tasks are
+/// spawned through the server binary, which applies them and then execs the
+/// real program (see `launch`).
+pub const LAUNCH_ARG: &str = "--launch-with-limits";
+
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+const CONTROLLERS: [&str; 3] = ["memory", "cpu", "pids"];
+/// cpu.max period, in microseconds.
+const CPU_PERIOD: u64 = 100_000;
+
+/// What the launcher does before exec'ing the task.
+#[derive(Serialize, Deserialize)]
+struct LaunchPlan {
+    cgroup: Option<PathBuf>,
+    limits: ResourceLimits,
+}
+
+/// Rejects values the kernel would, and limits `cgroups` (the server's, if
+/// any) can't enforce and no rlimit stands in for.
+pub fn validate(limits: &ResourceLimits, cgroups: Option<&Cgroups>) -> Result<()> {
+    if limits.memory_mb == Some(0) || limits.cpu_percent == Some(0) || limits.max_pids == Some(0) {
+        return Err(anyhow!("Limits must be greater than zero"));
+    }
+    // RLIMIT_NPROC would count every process of the server's user.
+    if limits.max_pids.is_some() && !cgroups.is_some_and(|c| c.controllers.iter().any(|c| c == "pids")) {
+        return Err(anyhow!("max_pids needs a delegated cgroup v2 with the pids controller, which this server doesn't have"));
+    }
+    if limits.cpu_weight.is_some_and(|w| !(1..=10000).contains(&w)) {
+        return Err(anyhow!("cpu_weight must be between 1 and 10000"));
+    }
+    if limits.nice.is_some_and(|n| !(-20..=19).contains(&n)) {
+        return Err(anyhow!("nice must be between -20 and 19"));
+    }
+    if limits.io_level.is_some_and(|l| l > 7) {
+        return Err(anyhow!("io_level must be between 0 and 7"));
+    }
+    if limits.io_level.is_some() && limits.io_class.is_none() {
+        return Err(anyhow!("io_level needs an io_class"));
+    }
+    Ok(())
+}
+
+/// Nothing to apply, so no need for the launcher.
+pub fn is_empty(limits: &ResourceLimits) -> bool {
+    limits.memory_mb.is_none()
+        && limits.cpu_percent.is_none()
+        && limits.cpu_weight.is_none()
+        && limits.max_pids.is_none()
+        && limits.nice.is_none()
+        && limits.io_class.is_none()
+}
+
+/// The cgroup v2 subtree delegated to the server, which tasks get their
+/// own cgroups in.
+///
+/// cgroup v2 allows no processes in a cgroup that hands controllers down
+/// to its children, so the server moves itself into a `server` leaf and
+/// each run gets a `task-<run id>` sibling.
+pub struct Cgroups {
+    root: PathBuf,
+    controllers: Vec<String>,
+}
+
+impl Cgroups {
+    /// `None` when there is nothing usable: no cgroup v2, or the server's
+    /// cgroup isn't delegated to it (under systemd, `Delegate=yes`).
+    pub fn init() -> Option<Self> {
+        match Self::try_init() {
+            Ok(cgroups) => {
+                tracing::info!("Task limits use cgroups under {} ({})", cgroups.root.display(), cgroups.controllers.join(", "));
+                Some(cgroups)
+            }
+            Err(e) => {
+                tracing::info!("Task limits fall back to rlimits: {:#}", e);
+                None
+            }
+        }
+    }
+
+    fn try_init() -> Result<Self> {
+        let own = fs::read_to_string("/proc/self/cgroup").context("Can't read /proc/self/cgroup")?;
+        let path = own.lines()
+            .find_map(|l| l.strip_prefix("0::"))
+            .ok_or_else(|| anyhow!("Not on cgroup v2"))?;
+        let mut root = Path::new(CGROUP_MOUNT).join(path.trim_start_matches('/'));
+        // Where an earlier server in this cgroup already moved itself.
+        if root.file_name().is_some_and(|n| n == "server") {
+            root.pop();
+        }
+        let available = fs::read_to_string(root.join("cgroup.controllers"))
+            .with_context(|| format!("No cgroup at {}", root.display()))?;
+
+        let server = root.join("server");
+        fs::create_dir_all(&server)
+            .with_context(|| format!("{} is not delegated to this user", root.display()))?;
+        fs::write(server.join("cgroup.procs"), std::process::id().to_string())
+            .with_context(|| format!("Can't move the server into {}", server.display()))?;
+
+        let controllers: Vec<String> = CONTROLLERS.iter()
+            .filter(|c| available.split_whitespace().any(|a| a == **c))
+            .map(|c| c.to_string())
+            .collect();
+        let enable: Vec<String> = controllers.iter().map(|c| format!("+{}", c)).collect();
+        fs::write(root.join("cgroup.subtree_control"), enable.join(" "))
+            .with_context(|| format!("Can't enable controllers in {}", root.display()))?;
+
+        // Left behind by runs that ended while no server was watching.
+        // Ones still holding processes won't go.
+        if let Ok(entries) = fs::read_dir(&root) {
+            for entry in entries.flatten() {
+                if entry.file_name().to_string_lossy().starts_with("task-") {
+                    let _ = fs::remove_dir(entry.path());
+                }
+            }
+        }
+
+        Ok(Self { root, controllers })
+    }
+
+    /// The cgroup of a run that was started by an earlier server, if it
+    /// had one.
+    pub fn find(&self, run_id: &str) -> Option<Cgroup> {
+        let path = self.root.join(format!("task-{}", run_id));
+        path.is_dir().then_some(Cgroup { path })
+    }
+
+    /// A fresh cgroup for one run, with `limits` applied.
+    pub fn create(&self, run_id: &str, limits: &ResourceLimits) -> Result<Cgroup> {
+        let cgroup = Cgroup { path: self.root.join(format!("task-{}", run_id)) };
+        fs::create_dir(&cgroup.path)
+            .with_context(|| format!("Failed to create cgroup {}", cgroup.path.display()))?;
+        if let Err(e) = self.apply(&cgroup, limits) {
+            let _ = fs::remove_dir(&cgroup.path);
+            return Err(e);
+        }
+        Ok(cgroup)
+    }
+
+    fn apply(&self, cgroup: &Cgroup, limits: &ResourceLimits) -> Result<()> {
+        let mut settings = Vec::new();
+        if let Some(mb) = limits.memory_mb {
+            settings.push(("memory", "memory.max", (u64::from(mb) << 20).to_string()));
+            // Otherwise a task over its limit swaps instead of being stopped.
+            if cgroup.path.join("memory.swap.max").exists() {
+                settings.push(("memory", "memory.swap.max", "0".to_string()));
+            }
+        }
+        if let Some(percent) = limits.cpu_percent {
+            settings.push(("cpu", "cpu.max", format!("{} {}", u64::from(percent) * CPU_PERIOD / 100, CPU_PERIOD)));
+        }
+        if let Some(weight) = limits.cpu_weight {
+            settings.push(("cpu", "cpu.weight", weight.to_string()));
+        }
+        if let Some(pids) = limits.max_pids {
+            settings.push(("pids", "pids.max", pids.to_string()));
+        }
+
+        for (controller, file, value) in settings {
+            if !self.controllers.iter().any(|c| c == controller) {
+                return Err(anyhow!("The {} cgroup controller is not available", controller));
+            }
+            fs::write(cgroup.path.join(file), &value)
+                .with_context(|| format!("Failed to set {} to {}", file, value))?;
+        }
+        Ok(())
+    }
+}
+
+/// The cgroup of one run.
+#[derive(Clone)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Whether the kernel OOM-killed anything in the cgroup.
+    pub fn oom_killed(&self) -> bool {
+        let Ok(events) = fs::read_to_string(self.path.join("memory.events")) else { return false };
+        events.lines()
+            .filter_map(|l| l.strip_prefix("oom_kill "))
+            .any(|n| n.trim().parse::<u64>().is_ok_and(|n| n > 0))
+    }
+
+    /// Freeze or thaw every process in the cgroup.
+    pub fn set_frozen(&self, frozen: bool) -> Result<()> {
+        fs::write(self.path.join("cgroup.freeze"), if frozen { "1" } else { "0" })
+            .with_context(|| format!("Failed to {} cgroup {}", if frozen { "freeze" } else { "thaw" }, self.path.display()))
+    }
+
+    /// Kill anything still in the cgroup, then remove it.
+    pub async fn remove(self) {
+        // cgroup.kill needs Linux 5.14; older kernels rely on the process
+        // group having been killed already.
+        let _ = fs::write(self.path.join("cgroup.kill"), "1");
+        for _ in 0..10 {
+            match fs::remove_dir(&self.path) {
+                Ok(()) => return,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+                // Busy until the killed processes are gone.
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+        tracing::warn!("Failed to remove cgroup {}", self.path.display());
+    }
+}
+
+/// The program and args that run `program` under `limits`, through the
+/// launcher.
+pub fn wrap(limits: &ResourceLimits, cgroup: Option<&Cgroup>, program: String, args: Vec<String>) -> Result<(String, Vec<String>)> {
+    if cgroup.is_none() && (limits.cpu_percent.is_some() || limits.cpu_weight.is_some()) {
+        tracing::warn!("CPU limits need a delegated cgroup v2; running without them");
+    }
+    // Only from tasks created while the server still had cgroups.
+    if cgroup.is_none() && limits.max_pids.is_some() {
+        tracing::warn!("max_pids needs a delegated cgroup v2; running without it");
+    }
+    let exe = std::env::current_exe().context("Can't find the server binary")?;
+    let plan = LaunchPlan { cgroup: cgroup.map(|c| c.path.clone()), limits: limits.clone() };
+
+    let mut wrapped = vec![LAUNCH_ARG.to_string(), serde_json::to_string(&plan)?, program];
+    wrapped.extend(args);
+    Ok((exe.to_string_lossy().into_owned(), wrapped))
+}
+
+/// The launcher: apply the plan to this process and exec the task. Runs
+/// in the task's PTY, so errors end up in its log.
+pub fn launch() -> ! {
+    let e = match try_launch() {
+        Ok(never) => match never {},
+        Err(e) => e,
+    };
+    eprintln!("{:#}", e);
+    // What a shell exits with for a command it can't find.
+    let not_found = e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound);
+    std::process::exit(if not_found { 127 } else { 1 })
+}
+
+fn try_launch() -> Result<std::convert::Infallible> {
+    let mut args = std::env::args().skip(2);
+    let plan: LaunchPlan = serde_json::from_str(&args.next().ok_or_else(|| anyhow!("Missing launch plan"))?)
+        .context("Invalid launch plan")?;
+    let program = args.next().ok_or_else(|| anyhow!("Missing program"))?;
+    let limits = &plan.limits;
+
+    match &plan.cgroup {
+        Some(cgroup) => {
+            fs::write(cgroup.join("cgroup.procs"), std::process::id().to_string())
+                .with_context(|| format!("Failed to join cgroup {}", cgroup.display()))?;
+        }
+        None => {
+            // Not RLIMIT_AS: CUDA and the JAX and PyTorch allocators reserve
+            // far more address space than they ever touch.
+            if let Some(mb) = limits.memory_mb {
+                set_rlimit(libc::RLIMIT_DATA, u64::from(mb) << 20).context("Failed to limit memory")?;
+            }
+        }
+    }
+
+    if let Some(nice) = limits.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+            return Err(std::io::Error::last_os_error()).with_context(|| format!("Failed to set nice level {}", nice));
+        }
+    }
+    if let Some(class) = limits.io_class {
+        set_ioprio(class, limits.io_level.unwrap_or(4))?;
+    }
+
+    Err(std::process::Command::new(&program).args(args).exec())
+        .with_context(|| format!("Failed to run {}", program))
+}
+
+fn set_rlimit(resource: libc::__rlimit_resource_t, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+    if unsafe { libc::setrlimit(resource, &limit) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `ioprio_set(2)`, which libc has no wrapper for.
+fn set_ioprio(class: IoClass, level: u8) -> Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    let class = match class {
+        IoClass::Realtime => 1,
+        IoClass::BestEffort => 2,
+        IoClass::Idle => 3,
+    };
+    let prio = (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level);
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to set I/O priority");
+    }
+    Ok(())
+}
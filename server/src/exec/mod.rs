@@ -1,111 +1,855 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc::RecvTimeoutError;
 use std::path::PathBuf;
-use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem, SlavePty, MasterPty};
+use std::time::{Duration, Instant};
+use portable_pty::{Child, CommandBuilder, NativePtySystem, PtySize, PtySystem, MasterPty};
 use tokio::sync::{RwLock, broadcast};
-use crate::core::models::{Task, TaskStatus};
+use crate::core::models::{ResourceLimits, RetryPolicy, Run, Task, TaskEvent, TaskStatus};
+use crate::secrets::{REDACT_HOLD, Redactor, SecretStore};
+use uuid::Uuid;
 use sqlx::SqlitePool;
-use anyhow::{Result, Context};
-use std::io::{Read, Write};
+use anyhow::{Result, Context, anyhow};
+use std::io::{Read, Seek, SeekFrom, Write};
 
-pub mod envs; 
+pub mod envs;
+pub mod gpus;
+pub mod limits;
+pub mod pause;
+pub mod recover;
+pub mod retry;
+pub mod scheduler;
+pub mod signals;
+
+/// How long `stop` waits after SIGTERM before sending SIGKILL.
+pub const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// Bytes of output replayed to a terminal client when it attaches.
+pub const SCROLLBACK_BYTES: usize = 64 * 1024;
+
+/// Live PTY output: a broadcast channel plus a ring buffer of recent bytes.
+///
+/// Both are updated under the same lock so a client attaching mid-stream gets
+/// the scrollback and a receiver with no gap or overlap between them.
+pub struct TaskOutput {
+    tx: broadcast::Sender<Vec<u8>>,
+    scrollback: Mutex<VecDeque<u8>>,
+    last_output: Mutex<Instant>,
+}
+
+impl TaskOutput {
+    fn new() -> Self {
+        let (tx, _rx) = broadcast::channel(100);
+        Self {
+            tx,
+            scrollback: Mutex::new(VecDeque::with_capacity(SCROLLBACK_BYTES)),
+            last_output: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn publish(&self, data: &[u8]) {
+        *self.last_output.lock().unwrap() = Instant::now();
+        let mut sb = self.scrollback.lock().unwrap();
+        sb.extend(data);
+        let excess = sb.len().saturating_sub(SCROLLBACK_BYTES);
+        sb.drain(..excess);
+        let _ = self.tx.send(data.to_vec());
+    }
+
+    pub fn attach(&self) -> (Vec<u8>, broadcast::Receiver<Vec<u8>>) {
+        let sb = self.scrollback.lock().unwrap();
+        (sb.iter().copied().collect(), self.tx.subscribe())
+    }
+
+    /// Restart the idle clock without any output.
+    fn touch(&self) {
+        *self.last_output.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the task last wrote anything, or since it started.
+    fn idle_for(&self) -> Duration {
+        self.last_output.lock().unwrap().elapsed()
+    }
+}
 
 pub struct RunningTask {
     pub master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
+    /// portable-pty hands out the writer only once, so it lives here and is
+    /// shared by every client typing into the task.
+    pub writer: Arc<Mutex<Box<dyn Write + Send>>>,
     pub pid: Option<u32>,
-    pub output_tx: broadcast::Sender<Vec<u8>>,
+    pub output: Arc<TaskOutput>,
+    /// Last signal sent by `stop`; set means the exit was requested.
+    pub stop_signal: Arc<Mutex<Option<i32>>>,
+    /// Set when signals go through the environment rather than to `pid`'s
+    /// group (see `EnvironmentBackend::signal_command`).
+    pub signal_via: Option<Vec<String>>,
+    pub pause: Arc<pause::PauseClock>,
+    pub cgroup: Option<limits::Cgroup>,
+}
+
+/// A task's live process, wherever it came from.
+#[derive(Clone)]
+struct LiveProcess {
+    pid: Option<u32>,
+    stop_signal: Arc<Mutex<Option<i32>>>,
+    signal_via: Option<Vec<String>>,
+    pause: Arc<pause::PauseClock>,
+    cgroup: Option<limits::Cgroup>,
+}
+
+impl LiveProcess {
+    /// Deliver `sig` to the task, not just to the process that was spawned.
+    async fn signal(&self, sig: i32) -> Result<()> {
+        let pid = self.pid.ok_or_else(|| anyhow!("Task has no pid"))?;
+        match &self.signal_via {
+            Some(command) => signals::run_signal_command(command, sig).await,
+            None => signals::kill_group(pid, sig),
+        }
+    }
+}
+
+/// What the supervisor needs to know about the run it is watching.
+struct Attempt {
+    task_id: String,
+    run_id: String,
+    retry: u32,
+    policy: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    output: Arc<TaskOutput>,
+    cgroup: Option<limits::Cgroup>,
+    pause: Arc<pause::PauseClock>,
+    gpus: Option<gpus::GpuReservation>,
+}
+
+impl Attempt {
+    /// Which limit, if any, the run has gone past. Time spent paused
+    /// doesn't count.
+    fn exceeded(&self, started: Instant) -> Option<&'static str> {
+        if self.pause.is_paused() {
+            None
+        } else if self.timeout.is_some_and(|t| started.elapsed().saturating_sub(self.pause.total()) >= t) {
+            Some("timeout")
+        } else if self.idle_timeout.is_some_and(|t| self.output.idle_for() >= t) {
+            Some("idle timeout")
+        } else {
+            None
+        }
+    }
 }
 
 pub struct TaskManager {
     pool: SqlitePool,
     log_root: PathBuf,
     pub tasks: Arc<RwLock<HashMap<String, RunningTask>>>,
+    /// Tasks still running from before a restart; see `recover`.
+    adopted: Arc<RwLock<HashMap<String, recover::AdoptedTask>>>,
     pty_sys: NativePtySystem,
+    events: broadcast::Sender<TaskEvent>,
+    stop_grace: Duration,
+    secrets: Option<Arc<SecretStore>>,
+    envs: Arc<envs::EnvRegistry>,
+    /// Where tasks with limits get their cgroups; rlimits are used without.
+    cgroups: Option<limits::Cgroups>,
+    gpus: Arc<gpus::GpuAllocator>,
+    /// Tasks between the "already running" check and their entry in `tasks`.
+    starting: Mutex<HashSet<String>>,
+}
+
+/// A task's place in `TaskManager::starting`, given up when dropped.
+struct StartSlot<'a> {
+    starting: &'a Mutex<HashSet<String>>,
+    id: String,
 }
 
+impl Drop for StartSlot<'_> {
+    fn drop(&mut self) {
+        self.starting.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Returned by `spawn` when the task already has a live process, so callers
+/// can tell a lost race from a failed start.
+#[derive(Debug)]
+pub struct AlreadyRunning;
+
+impl std::fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Task is already running")
+    }
+}
+
+impl std::error::Error for AlreadyRunning {}
+
 impl TaskManager {
     pub fn new(pool: SqlitePool, log_root: PathBuf) -> Self {
-        Self { 
-            pool, 
+        let (events, _) = broadcast::channel(256);
+        Self {
+            pool,
             log_root,
             tasks: Arc::new(RwLock::new(HashMap::new())),
+            adopted: Arc::new(RwLock::new(HashMap::new())),
             pty_sys: NativePtySystem::default(),
+            events,
+            stop_grace: DEFAULT_STOP_GRACE,
+            secrets: None,
+            envs: Arc::new(envs::EnvRegistry::builtin(&[], "docker")),
+            cgroups: None,
+            gpus: Arc::new(gpus::GpuAllocator::new(Arc::new(gpus::StaticInventory::default()))),
+            starting: Mutex::new(HashSet::new()),
         }
     }
 
-    pub async fn spawn(&self, id: &str) -> Result<()> {
+    pub fn with_stop_grace(mut self, grace: Duration) -> Self {
+        self.stop_grace = grace;
+        self
+    }
+
+    pub fn with_secrets(mut self, secrets: Arc<SecretStore>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    pub fn with_envs(mut self, envs: envs::EnvRegistry) -> Self {
+        self.envs = Arc::new(envs);
+        self
+    }
+
+    pub fn with_cgroups(mut self, cgroups: Option<limits::Cgroups>) -> Self {
+        self.cgroups = cgroups;
+        self
+    }
+
+    pub fn with_gpus(mut self, inventory: Arc<dyn gpus::GpuInventory>) -> Self {
+        self.gpus = Arc::new(gpus::GpuAllocator::new(inventory));
+        self
+    }
+
+    pub fn cgroups(&self) -> Option<&limits::Cgroups> {
+        self.cgroups.as_ref()
+    }
+
+    pub fn gpus(&self) -> &gpus::GpuAllocator {
+        &self.gpus
+    }
+
+    pub fn envs(&self) -> &Arc<envs::EnvRegistry> {
+        &self.envs
+    }
+
+    /// Subscribe to task state transitions.
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start a new run of a task. Each run gets its own row in `runs` and its
+    /// own log file; the task row is updated to point at it.
+    pub async fn spawn(self: &Arc<Self>, id: &str) -> Result<()> {
+        self.spawn_attempt(id, 0).await
+    }
+
+    /// Start the next attempt of a `Retrying` task, unless it was stopped or
+    /// restarted by hand while waiting out the backoff. False if nothing was
+    /// started for that reason.
+    pub async fn spawn_retry(self: &Arc<Self>, id: &str) -> Result<bool> {
+        let last: Option<(Option<i64>,)> = sqlx::query_as(
+            "SELECT r.retry FROM tasks t LEFT JOIN runs r ON r.id = t.last_run_id WHERE t.id = ? AND t.status = 'Retrying'"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        match last {
+            Some((retry,)) => self.spawn_attempt(id, retry.unwrap_or(0) as u32 + 1).await.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// `retry` is 0 for a fresh start and counts up as the retry policy
+    /// starts further attempts.
+    async fn spawn_attempt(self: &Arc<Self>, id: &str, retry: u32) -> Result<()> {
+        // Claimed before looking at `tasks`, and only given up once the run
+        // is in there, so two starts can't both get past this point.
+        if !self.starting.lock().unwrap().insert(id.to_string()) {
+            return Err(AlreadyRunning.into());
+        }
+        let _slot = StartSlot { starting: &self.starting, id: id.to_string() };
+        if self.tasks.read().await.contains_key(id) || self.adopted.read().await.contains_key(id) {
+            return Err(AlreadyRunning.into());
+        }
+
         let task: Task = sqlx::query_as("SELECT * FROM tasks WHERE id = ?")
             .bind(id)
             .fetch_one(&self.pool)
             .await
             .context("Task not found in DB")?;
+        // What the task will point at once the run is recorded, so backends
+        // see the same run here as after a restart.
+        let run_id = Uuid::new_v4().to_string();
+        let task = Task { last_run_id: Some(run_id.clone()), ..task };
 
-        let (prog, args) = envs::build_command(&task)?;
-        let mut cmd = CommandBuilder::new(prog);
-        cmd.args(&args);
-        
-        if let Some(ref cwd) = task.cwd.as_str().into() {
-             cmd.cwd(cwd.to_string());
+        // Secrets go in last so nothing else overrides them, and their values
+        // are masked in everything the task prints.
+        let mut secrets = Vec::new();
+        for (var, name) in task.secret_env.iter() {
+            let store = self.secrets.as_ref().ok_or_else(|| anyhow!("No secrets store configured"))?;
+            secrets.push((var.clone(), store.reveal(name).await?));
         }
 
-        for (k, v) in envs::get_env_vars(&task)? {
-            cmd.env(k, v);
+        // Held until the run ends, including on every error path below.
+        let gpus = match task.gpus.filter(|n| *n > 0) {
+            Some(n) => Some(self.gpus.reserve(n, task.min_free_gpu_mem_mb)?),
+            None => None,
+        };
+        let gpu_devices = gpus.as_ref().map(|g| g.visible_devices());
+
+        let mut overrides = secrets.clone();
+        if let Some(devices) = &gpu_devices {
+            overrides.push(("CUDA_VISIBLE_DEVICES".to_string(), devices.clone()));
         }
+        let launch = self.envs.launch(&task, &overrides)?;
 
         // PTY Setup
-        let pair = self.pty_sys.openpty(PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 })
+        let size = PtySize {
+            rows: task.pty_rows.unwrap_or(24),
+            cols: task.pty_cols.unwrap_or(80),
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        let pair = self.pty_sys.openpty(size)
             .context("Failed to open PTY")?;
 
-        let child = pair.slave.spawn_command(cmd).context("Failed to spawn child")?;
+        let task_limits = task.limits.as_ref().map(|l| &l.0).filter(|l| !limits::is_empty(l));
+        let cgroup = match (task_limits, &self.cgroups) {
+            (Some(l), Some(cgroups)) => Some(cgroups.create(&run_id, l)?),
+            _ => None,
+        };
+        let spawned = Self::command(&task, launch, task_limits, cgroup.as_ref())
+            .and_then(|cmd| pair.slave.spawn_command(cmd).context("Failed to spawn child"));
+        let child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(cgroup) = cgroup {
+                    cgroup.remove().await;
+                }
+                return Err(e);
+            }
+        };
 
         let pid = child.process_id();
+        let pid_start_time = pid.and_then(recover::process_start_time);
+        let log_path = self.log_path(&run_id);
 
-        // Update DB
-        sqlx::query("UPDATE tasks SET status = 'Running', started_at = datetime('now'), pid = ? WHERE id = ?")
-            .bind(pid)
-            .bind(id)
-            .execute(&self.pool)
-            .await
-            .context("Failed to update task status")?;
+        // Everything that can still fail now that the child is running. If
+        // any of it does, the child is killed rather than left unsupervised.
+        let io = pair.master.try_clone_reader().context("Failed to clone PTY reader")
+            .and_then(|reader| Ok((reader, pair.master.take_writer().context("Failed to take writer from PTY")?)));
+        let recorded = match io {
+            Err(e) => Err(e),
+            Ok(io) => async {
+                let mut tx = self.pool.begin().await?;
+                sqlx::query(
+                    "INSERT INTO runs (id, task_id, attempt, retry, status, started_at, pid, pid_start_time, log_file, gpu_devices) \
+                     SELECT ?, ?, COALESCE(MAX(attempt), 0) + 1, ?, 'Running', datetime('now'), ?, ?, ?, ? FROM runs WHERE task_id = ?"
+                )
+                    .bind(&run_id)
+                    .bind(id)
+                    .bind(retry)
+                    .bind(pid)
+                    .bind(pid_start_time)
+                    .bind(log_path.to_string_lossy())
+                    .bind(&gpu_devices)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await
+                    .context("Failed to record run")?;
+                sqlx::query("UPDATE tasks SET status = 'Running', started_at = datetime('now'), ended_at = NULL, exit_code = NULL, signal = NULL, reason = NULL, retry_at = NULL, pid = ?, last_run_id = ?, gpu_devices = ? WHERE id = ?")
+                    .bind(pid)
+                    .bind(&run_id)
+                    .bind(&gpu_devices)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await
+                    .context("Failed to update task status")?;
+                tx.commit().await?;
+                Ok(io)
+            }.await,
+        };
+        let (mut reader, writer) = match recorded {
+            Ok(io) => io,
+            Err(e) => {
+                self.abandon(&task, child, cgroup).await;
+                return Err(e);
+            }
+        };
 
         // Log Streaming
-        let mut reader = pair.master.try_clone_reader().context("Failed to clone PTY reader")?;
-        let log_root = self.log_root.clone();
-        let id_str = id.to_string();
-        let (tx, _rx) = broadcast::channel(100);
-        let tx_clone = tx.clone();
-        
+        let output = Arc::new(TaskOutput::new());
+        let output_clone = output.clone();
+
+        let mut redactor = Redactor::new(secrets.into_iter().map(|(_, value)| value));
+
+        // The reader blocks, so output goes through a channel to a thread
+        // that can tell when the task has gone quiet and let the redactor's
+        // held-back tail through.
+        let (chunks, received) = std::sync::mpsc::channel::<Vec<u8>>();
         std::thread::spawn(move || {
-            let log_path = log_root.join(format!("{}.log", id_str));
-            let mut f = std::fs::OpenOptions::new().create(true).append(true).open(log_path).unwrap(); 
             let mut buf = [0u8; 4096];
             while let Ok(n) = reader.read(&mut buf) {
-                if n == 0 { break; }
-                let data = &buf[..n];
+                if n == 0 || chunks.send(buf[..n].to_vec()).is_err() { break; }
+            }
+        });
+        std::thread::spawn(move || {
+            let mut f = std::fs::OpenOptions::new().create(true).append(true).open(log_path).unwrap();
+            let mut emit = |data: Vec<u8>| {
+                if data.is_empty() { return; }
                 // Log to file
-                let _ = f.write_all(data);
+                let _ = f.write_all(&data);
                 // Broadcast to WS
-                let _ = tx_clone.send(data.to_vec());
+                output_clone.publish(&data);
+            };
+            loop {
+                match received.recv_timeout(REDACT_HOLD) {
+                    Ok(chunk) => emit(redactor.feed(&chunk)),
+                    Err(RecvTimeoutError::Timeout) => emit(redactor.finish()),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
             }
+            emit(redactor.finish());
         });
 
+        let stop_signal = Arc::new(Mutex::new(None));
+        let pause = Arc::new(pause::PauseClock::default());
         self.tasks.write().await.insert(id.to_string(), RunningTask {
             master: Arc::new(Mutex::new(pair.master)),
+            writer: Arc::new(Mutex::new(writer)),
             pid,
-            output_tx: tx,
+            output: output.clone(),
+            stop_signal: stop_signal.clone(),
+            signal_via: self.envs.signal_command(&task),
+            pause: pause.clone(),
+            cgroup: cgroup.clone(),
+        });
+
+        let _ = self.events.send(TaskEvent {
+            task_id: id.to_string(),
+            run_id: Some(run_id.clone()),
+            status: TaskStatus::Running,
+            exit_code: None,
+            signal: None,
         });
 
+        let attempt = Attempt {
+            task_id: id.to_string(),
+            run_id,
+            retry,
+            policy: task.retry_policy.map(|p| p.0),
+            // Zero means no limit, same as leaving it out.
+            timeout: task.timeout_secs.filter(|s| *s > 0).map(|s| Duration::from_secs(s.into())),
+            idle_timeout: task.idle_timeout_secs.filter(|s| *s > 0).map(|s| Duration::from_secs(s.into())),
+            output,
+            cgroup,
+            pause,
+            gpus,
+        };
+        self.supervise(attempt, child, stop_signal);
+
         Ok(())
     }
 
-    pub async fn write_stdin(&self, id: &str, data: &[u8]) -> Result<()> {
-        let map = self.tasks.read().await;
-        if let Some(t) = map.get(id) {
-            let mut m = t.master.lock().unwrap();
-            // Try take_writer which is common in portable-pty for writing to stdin
-            let mut writer = m.take_writer().context("Failed to take writer from PTY")?;
-            writer.write_all(data).context("Failed to write to PTY")?;
+    /// Kill and reap a child whose run couldn't be recorded, and remove its
+    /// cgroup. Its GPUs go back when the caller drops the reservation.
+    async fn abandon(&self, task: &Task, mut child: Box<dyn Child + Send + Sync>, cgroup: Option<limits::Cgroup>) {
+        if let Some(command) = self.envs.signal_command(task) {
+            let _ = signals::run_signal_command(&command, libc::SIGKILL).await;
+        }
+        if let Some(pid) = child.process_id() {
+            let _ = signals::kill_group(pid, libc::SIGKILL);
         }
+        let _ = child.kill();
+        let _ = tokio::task::spawn_blocking(move || child.wait()).await;
+        if let Some(cgroup) = cgroup {
+            cgroup.remove().await;
+        }
+    }
+
+    /// The command to spawn for a task, through the limits launcher if it
+    /// has any.
+    fn command(task: &Task, launch: envs::Launch, task_limits: Option<&ResourceLimits>, cgroup: Option<&limits::Cgroup>) -> Result<CommandBuilder> {
+        let (program, args) = match task_limits {
+            Some(l) => limits::wrap(l, cgroup, launch.program, launch.args)?,
+            None => (launch.program, launch.args),
+        };
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(&args);
+        cmd.cwd(&task.cwd);
+
+        if task.clear_env {
+            // Keep PATH: without it neither the program nor the env
+            // type's own binaries (conda, uv) can be found.
+            cmd.env_clear();
+            if let Some(path) = std::env::var_os("PATH") {
+                cmd.env("PATH", path);
+            }
+        }
+        for (k, v) in launch.env {
+            cmd.env(k, v);
+        }
+        Ok(cmd)
+    }
+
+    /// Wait for the child in the background, then record how it ended.
+    ///
+    /// `Child::wait` blocks, so it runs on the blocking pool. Once the child is
+    /// reaped we write the terminal status back to the DB, drop the task from
+    /// the running map (which closes the PTY master) and notify listeners.
+    /// A task that exits after `stop` is recorded as `Stopped`. A failure the
+    /// retry policy covers leaves the task `Retrying` with a `retry_at` after
+    /// the backoff, for the scheduler to start again from there.
+    ///
+    /// While waiting it also enforces the task's timeouts, stopping the task
+    /// the same way `stop` does and recording it as `TimedOut`.
+    fn supervise(self: &Arc<Self>, attempt: Attempt, mut child: Box<dyn Child + Send + Sync>, stop_signal: Arc<Mutex<Option<i32>>>) {
+        let manager = self.clone();
+        let pid = child.process_id();
+
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let watched = attempt.timeout.is_some() || attempt.idle_timeout.is_some();
+            let mut wait = tokio::task::spawn_blocking(move || child.wait());
+            let mut watchdog = tokio::time::interval(Duration::from_secs(1));
+            let mut timed_out = false;
+            let waited = loop {
+                tokio::select! {
+                    res = &mut wait => break res,
+                    _ = watchdog.tick(), if watched && !timed_out => {
+                        let Some(reason) = attempt.exceeded(started) else { continue };
+                        // Already on its way out at someone's request.
+                        if stop_signal.lock().unwrap().is_some() {
+                            continue;
+                        }
+                        let Some(process) = manager.live_process(&attempt.task_id).await else { continue };
+                        tracing::warn!("Task {} hit its {}, stopping it", attempt.task_id, reason);
+                        timed_out = true;
+                        if let Err(e) = manager.terminate(&attempt.task_id, process, manager.stop_grace).await {
+                            tracing::error!("{:?}", e);
+                        }
+                    }
+                }
+            };
+
+            let Attempt { task_id: id, run_id, retry, policy, cgroup, gpus, .. } = attempt;
+            // Free before a retry, which reserves its own.
+            drop(gpus);
+            let stopped_by = *stop_signal.lock().unwrap();
+
+            let (mut status, exit_code, signal) = match waited {
+                Ok(Ok(exit)) => {
+                    let signal = exit.signal().map(|desc| match signals::from_description(desc) {
+                        Some(sig) => signals::signal_name(sig),
+                        None => desc.to_string(),
+                    });
+                    // portable-pty reports 1 for signal deaths; that code means nothing.
+                    let exit_code = if signal.is_some() { None } else { Some(exit.exit_code() as i32) };
+                    let status = if exit.success() { TaskStatus::Completed } else { TaskStatus::Failed };
+                    (status, exit_code, signal)
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("Failed to wait on task {}: {:?}", id, e);
+                    (TaskStatus::Failed, None, None)
+                }
+                Err(e) => {
+                    tracing::error!("Supervisor for task {} panicked: {:?}", id, e);
+                    (TaskStatus::Failed, None, None)
+                }
+            };
+
+            let signal = match stopped_by {
+                Some(sig) => {
+                    status = TaskStatus::Stopped;
+                    // The leader is gone; don't leave its workers behind.
+                    if let Some(pid) = pid {
+                        let _ = signals::kill_group(pid, libc::SIGKILL);
+                    }
+                    Some(signals::signal_name(sig))
+                }
+                None => signal,
+            };
+            if timed_out {
+                status = TaskStatus::TimedOut;
+            }
+            // Otherwise all there is to go on is a SIGKILL or exit code 137.
+            let reason = (status == TaskStatus::Failed && cgroup.as_ref().is_some_and(|c| c.oom_killed()))
+                .then(|| "killed: out of memory".to_string());
+            if let Some(cgroup) = cgroup {
+                cgroup.remove().await;
+            }
+
+            let retry_delay = match &policy {
+                Some(p) if status == TaskStatus::Failed
+                    && retry < p.max_retries
+                    && retry::is_retryable(p, exit_code, signal.as_deref()) =>
+                {
+                    Some(retry::backoff_delay(p, retry + 1))
+                }
+                _ => None,
+            };
+            let task_status = if retry_delay.is_some() { TaskStatus::Retrying } else { status };
+
+            // Same columns on both: the run, and the task's copy of its latest run.
+            for (table, key, status) in [("runs", &run_id, status), ("tasks", &id, task_status)] {
+                let sql = format!("UPDATE {} SET status = ?, exit_code = ?, signal = ?, reason = ?, ended_at = datetime('now') WHERE id = ?", table);
+                if let Err(e) = sqlx::query(&sql)
+                    .bind(status)
+                    .bind(exit_code)
+                    .bind(&signal)
+                    .bind(&reason)
+                    .bind(key)
+                    .execute(&manager.pool)
+                    .await
+                {
+                    tracing::error!("Failed to record exit of task {} in {}: {:?}", id, table, e);
+                }
+            }
+            if let Some(delay) = retry_delay {
+                if let Err(e) = sqlx::query("UPDATE tasks SET retry_at = datetime('now', '+' || ? || ' seconds') WHERE id = ?")
+                    .bind(delay.as_secs_f64())
+                    .bind(&id)
+                    .execute(&manager.pool)
+                    .await
+                {
+                    tracing::error!("Failed to record retry time of task {}: {:?}", id, e);
+                }
+            }
+
+            manager.tasks.write().await.remove(&id);
+
+            match &reason {
+                Some(reason) => tracing::info!("Task {} finished: {:?} ({})", id, status, reason),
+                None => tracing::info!("Task {} finished: {:?} (exit code {:?})", id, status, exit_code),
+            }
+            let _ = manager.events.send(TaskEvent {
+                task_id: id.clone(),
+                run_id: Some(run_id),
+                status: task_status,
+                exit_code,
+                signal,
+            });
+            if let Some(delay) = retry_delay {
+                tracing::info!("Task {} will retry in {:?} (retry {})", id, delay, retry + 1);
+            }
+        });
+    }
+
+    fn publish(&self, id: &str, status: TaskStatus) {
+        let _ = self.events.send(TaskEvent {
+            task_id: id.to_string(),
+            run_id: None,
+            status,
+            exit_code: None,
+            signal: None,
+        });
+    }
+
+    /// Hand a task to the scheduler. Anything not currently running or
+    /// already queued can be queued, so finished tasks can be re-run.
+    pub async fn enqueue(&self, id: &str) -> Result<()> {
+        let res = sqlx::query("UPDATE tasks SET status = 'Queued', queued_at = datetime('now') WHERE id = ? AND status NOT IN ('Running', 'Paused', 'Queued')")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(anyhow!("Task not found, running or already queued"));
+        }
+        self.publish(id, TaskStatus::Queued);
+        Ok(())
+    }
+
+    pub async fn dequeue(&self, id: &str) -> Result<()> {
+        let res = sqlx::query("UPDATE tasks SET status = 'Pending', queued_at = NULL WHERE id = ? AND status = 'Queued'")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(anyhow!("Task is not queued"));
+        }
+        self.publish(id, TaskStatus::Pending);
         Ok(())
     }
+
+    /// Record a terminal status for a task that never got a process.
+    pub async fn mark_finished(&self, id: &str, status: TaskStatus) -> Result<()> {
+        sqlx::query("UPDATE tasks SET status = ?, ended_at = datetime('now') WHERE id = ?")
+            .bind(status)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        self.publish(id, status);
+        Ok(())
+    }
+
+    fn log_path(&self, run_id: &str) -> PathBuf {
+        self.log_root.join(format!("{}.log", run_id))
+    }
+
+    /// Scrollback plus a live output receiver for a running task. For a task
+    /// that is not running, the tail of its latest run's log and no receiver.
+    pub async fn attach(&self, id: &str) -> Result<(Vec<u8>, Option<broadcast::Receiver<Vec<u8>>>)> {
+        if let Some(t) = self.tasks.read().await.get(id) {
+            let (replay, rx) = t.output.attach();
+            return Ok((replay, Some(rx)));
+        }
+
+        let last_run: Option<(String,)> = sqlx::query_as(
+            "SELECT r.log_file FROM tasks t JOIN runs r ON r.id = t.last_run_id WHERE t.id = ?"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((log_file,)) = last_run else { return Ok((Vec::new(), None)) };
+
+        Ok((read_tail(std::path::Path::new(&log_file), SCROLLBACK_BYTES)?, None))
+    }
+
+    pub async fn runs(&self, id: &str) -> Result<Vec<Run>> {
+        Ok(sqlx::query_as("SELECT * FROM runs WHERE task_id = ? ORDER BY attempt")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    pub async fn run(&self, run_id: &str) -> Result<Option<Run>> {
+        Ok(sqlx::query_as("SELECT * FROM runs WHERE id = ?")
+            .bind(run_id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Leader pid -> task id for every live task, adopted ones included.
+    pub async fn task_pids(&self) -> HashMap<u32, String> {
+        let mut pids: HashMap<u32, String> = self.tasks.read().await.iter()
+            .filter_map(|(id, t)| Some((t.pid?, id.clone())))
+            .collect();
+        pids.extend(self.adopted.read().await.iter().map(|(id, t)| (t.pid, id.clone())));
+        pids
+    }
+
+    /// A task's live process, whether started by this server or adopted
+    /// from a previous one.
+    async fn live_process(&self, id: &str) -> Option<LiveProcess> {
+        if let Some(t) = self.tasks.read().await.get(id) {
+            return Some(LiveProcess {
+                pid: t.pid,
+                stop_signal: t.stop_signal.clone(),
+                signal_via: t.signal_via.clone(),
+                pause: t.pause.clone(),
+                cgroup: t.cgroup.clone(),
+            });
+        }
+        self.adopted.read().await.get(id).map(|t| LiveProcess {
+            pid: Some(t.pid),
+            stop_signal: t.stop_signal.clone(),
+            signal_via: t.signal_via.clone(),
+            pause: t.pause.clone(),
+            cgroup: t.cgroup.clone(),
+        })
+    }
+
+    /// SIGTERM the task's process group, then SIGKILL it if it is still
+    /// around once `grace` (or the manager default) has passed.
+    pub async fn stop(&self, id: &str, grace: Option<Duration>) -> Result<()> {
+        let Some(process) = self.live_process(id).await else {
+            // Between retries there is no process, just a pending attempt.
+            let res = sqlx::query("UPDATE tasks SET status = 'Stopped' WHERE id = ? AND status = 'Retrying'")
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            if res.rows_affected() == 0 {
+                return Err(anyhow!("Task is not running"));
+            }
+            self.publish(id, TaskStatus::Stopped);
+            return Ok(());
+        };
+
+        self.terminate(id, process, grace.unwrap_or(self.stop_grace)).await
+    }
+
+    async fn terminate(&self, id: &str, process: LiveProcess, grace: Duration) -> Result<()> {
+        let pid = process.pid.ok_or_else(|| anyhow!("Task has no pid"))?;
+        *process.stop_signal.lock().unwrap() = Some(libc::SIGTERM);
+        process.signal(libc::SIGTERM).await?;
+        // A suspended task would only see the signal once resumed.
+        process.wake().await?;
+
+        let tasks = self.tasks.clone();
+        let adopted = self.adopted.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            let still_running = tasks.read().await.get(&id).is_some_and(|t| t.pid == Some(pid))
+                || adopted.read().await.get(&id).is_some_and(|t| t.pid == pid);
+            if still_running {
+                tracing::warn!("Task {} ignored SIGTERM for {:?}, sending SIGKILL", id, grace);
+                *process.stop_signal.lock().unwrap() = Some(libc::SIGKILL);
+                if let Err(e) = process.signal(libc::SIGKILL).await {
+                    tracing::error!("{:?}", e);
+                }
+                // Whatever happened to the task, don't leave what we
+                // spawned behind.
+                if process.signal_via.is_some() {
+                    if let Err(e) = signals::kill_group(pid, libc::SIGKILL) {
+                        tracing::error!("{:?}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Deliver an arbitrary signal to the task.
+    pub async fn signal(&self, id: &str, sig: i32) -> Result<()> {
+        let process = self.live_process(id).await.ok_or_else(|| anyhow!("Task is not running"))?;
+        process.signal(sig).await
+    }
+
+    pub async fn resize(&self, id: &str, rows: u16, cols: u16) -> Result<()> {
+        let map = self.tasks.read().await;
+        let t = map.get(id).ok_or_else(|| anyhow!("Task is not running"))?;
+        let m = t.master.lock().unwrap();
+        m.resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })
+            .context("Failed to resize PTY")
+    }
+
+    pub async fn write_stdin(&self, id: &str, data: &[u8]) -> Result<()> {
+        let writer = match self.tasks.read().await.get(id) {
+            Some(t) => t.writer.clone(),
+            None => return Err(anyhow!("Task is not running")),
+        };
+
+        // A child that isn't reading its input can fill the PTY buffer and
+        // block the write, so keep it off the async workers.
+        let data = data.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let mut w = writer.lock().unwrap();
+            w.write_all(&data)?;
+            w.flush()
+        })
+        .await?
+        .context("Failed to write to PTY")
+    }
+}
+
+/// The last `max` bytes of a file, or nothing if it doesn't exist.
+pub fn read_tail(path: &std::path::Path, max: usize) -> Result<Vec<u8>> {
+    let mut f = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to open task log"),
+    };
+    let len = f.metadata()?.len();
+    f.seek(SeekFrom::Start(len.saturating_sub(max as u64)))?;
+    let mut tail = Vec::new();
+    f.read_to_end(&mut tail)?;
+    Ok(tail)
 }
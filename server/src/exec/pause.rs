@@ -0,0 +1,133 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use crate::core::models::TaskStatus;
+use super::{LiveProcess, TaskManager};
+
+/// How long a run has been paused, so that timeouts only count the time it
+/// could actually run.
+#[derive(Default)]
+pub struct PauseClock {
+    state: Mutex<PauseState>,
+}
+
+#[derive(Default)]
+struct PauseState {
+    since: Option<Instant>,
+    earlier: Duration,
+}
+
+impl PauseClock {
+    /// A clock for a run that is paused already, as found after a restart.
+    pub fn paused() -> Self {
+        let clock = Self::default();
+        clock.pause();
+        clock
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().since.is_some()
+    }
+
+    /// False if it was paused already.
+    fn pause(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.since.is_some() {
+            return false;
+        }
+        state.since = Some(Instant::now());
+        true
+    }
+
+    /// False if it wasn't paused.
+    fn resume(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.since.take() {
+            Some(since) => {
+                state.earlier += since.elapsed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// All the time spent paused, including the current pause.
+    pub fn total(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state.earlier + state.since.map_or(Duration::ZERO, |s| s.elapsed())
+    }
+}
+
+impl LiveProcess {
+    /// Freeze or thaw the task: its whole cgroup when it has one, so nothing
+    /// that left the process group keeps running, otherwise with
+    /// SIGSTOP/SIGCONT. A task that runs outside the spawned process, such
+    /// as in a container, always gets the signals through the environment,
+    /// since its cgroup would only hold the runtime's client.
+    async fn suspend(&self, frozen: bool) -> Result<()> {
+        match (&self.cgroup, &self.signal_via) {
+            (Some(cgroup), None) => cgroup.set_frozen(frozen),
+            _ => self.signal(if frozen { libc::SIGSTOP } else { libc::SIGCONT }).await,
+        }
+    }
+
+    /// Let a paused task run again, so it can act on a signal it was just
+    /// sent.
+    pub(super) async fn wake(&self) -> Result<()> {
+        if self.pause.resume() {
+            self.suspend(false).await?;
+        }
+        Ok(())
+    }
+}
+
+impl TaskManager {
+    /// Suspend a running task until `resume`. It keeps its memory and its
+    /// place, but stops using CPU and no longer counts towards the queue's
+    /// concurrency.
+    pub async fn pause(&self, id: &str) -> Result<()> {
+        let process = self.live_process(id).await.ok_or_else(|| anyhow!("Task is not running"))?;
+        if process.stop_signal.lock().unwrap().is_some() {
+            return Err(anyhow!("Task is stopping"));
+        }
+        if !process.pause.pause() {
+            return Err(anyhow!("Task is already paused"));
+        }
+        if let Err(e) = process.suspend(true).await {
+            process.pause.resume();
+            return Err(e);
+        }
+        self.set_live_status(id, TaskStatus::Running, TaskStatus::Paused).await
+    }
+
+    pub async fn resume(&self, id: &str) -> Result<()> {
+        let process = self.live_process(id).await.ok_or_else(|| anyhow!("Task is not running"))?;
+        if !process.pause.is_paused() {
+            return Err(anyhow!("Task is not paused"));
+        }
+        // Going quiet while paused doesn't count against the idle timeout.
+        if let Some(t) = self.tasks.read().await.get(id) {
+            t.output.touch();
+        }
+        process.wake().await?;
+        self.set_live_status(id, TaskStatus::Paused, TaskStatus::Running).await
+    }
+
+    /// Moves the task and its latest run from one live status to another.
+    async fn set_live_status(&self, id: &str, from: TaskStatus, to: TaskStatus) -> Result<()> {
+        sqlx::query("UPDATE runs SET status = ? WHERE id = (SELECT last_run_id FROM tasks WHERE id = ?) AND status = ?")
+            .bind(to)
+            .bind(id)
+            .bind(from)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("UPDATE tasks SET status = ? WHERE id = ? AND status = ?")
+            .bind(to)
+            .bind(id)
+            .bind(from)
+            .execute(&self.pool)
+            .await?;
+        self.publish(id, to);
+        Ok(())
+    }
+}
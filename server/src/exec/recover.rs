@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crate::core::models::{Task, TaskStatus};
+use super::gpus::GpuReservation;
+use super::limits::Cgroup;
+use super::pause::PauseClock;
+use super::{TaskManager, signals};
+
+/// How often an adopted process is checked for having exited.
+const ADOPTED_POLL: Duration = Duration::from_secs(2);
+
+/// A task whose process outlived the server that started it.
+///
+/// The PTY went away with the old server, so all that is left is the pid:
+/// the task can be signalled and stopped, but not attached to, and its exit
+/// status can't be collected.
+///
+/// Only tasks that ignore SIGHUP get this far. Closing the old server's PTY
+/// master hangs up the task's terminal, and the kernel sends its session
+/// SIGHUP; anything else also needs the service manager to leave it running
+/// (see `KillMode` in task-mgr.service). Since the process isn't our child,
+/// nothing in /proc says how it ended: an adopted task always finishes
+/// `Lost` with no exit code, `Stopped` if it was stopped from here, or
+/// `TimedOut`.
+///
+/// The wall-clock timeout still holds, counted from the run's recorded
+/// start, though time it spent paused before the restart counts against it.
+/// The idle timeout doesn't: there is no output left to watch.
+pub struct AdoptedTask {
+    pub pid: u32,
+    /// When the run's `timeout_secs` runs out.
+    pub deadline: Option<DateTime<Utc>>,
+    pub stop_signal: Arc<Mutex<Option<i32>>>,
+    pub signal_via: Option<Vec<String>>,
+    pub pause: Arc<PauseClock>,
+    pub cgroup: Option<Cgroup>,
+}
+
+/// Start time of `pid` in clock ticks since boot, from `/proc/<pid>/stat`.
+/// Together with the pid it identifies a process across pid reuse.
+///
+/// `None` once the process has exited, including while it is a zombie: an
+/// adopted task's new parent isn't always quick to reap it.
+pub fn process_start_time(pid: u32) -> Option<i64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // comm can contain spaces and parens, so count fields from the last ')'.
+    // What follows starts at field 3 (state); starttime is field 22.
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    if matches!(fields.next()?, "Z" | "X") {
+        return None;
+    }
+    fields.nth(18)?.parse().ok()
+}
+
+impl TaskManager {
+    /// Reconcile tasks a previous server process left `Running` or `Paused`.
+    /// Call once at startup, before anything else spawns tasks. `Retrying`
+    /// tasks need nothing: their `retry_at` is in the DB for the scheduler.
+    ///
+    /// A recorded pid only counts as alive if its start time still matches,
+    /// so a recycled pid is never mistaken for the task. Runs recorded
+    /// without a start time can't be verified and are treated as gone.
+    pub async fn recover(self: &Arc<Self>) -> Result<()> {
+        let orphans: Vec<Task> = sqlx::query_as("SELECT * FROM tasks WHERE status IN ('Running', 'Paused')")
+            .fetch_all(&self.pool)
+            .await?;
+
+        for task in orphans {
+            let (id, run_id) = (task.id.clone(), task.last_run_id.clone());
+            let run = match &run_id {
+                Some(run_id) => self.run(run_id).await?,
+                None => None,
+            };
+            match run.as_ref().map(|r| (r.pid, r.pid_start_time)) {
+                Some((Some(pid), Some(start))) if process_start_time(pid) == Some(start) => {
+                    tracing::info!("Task {} (pid {}) survived the restart, adopting it", id, pid);
+                    let adopted = AdoptedTask {
+                        pid,
+                        deadline: task.timeout_secs.zip(run.as_ref())
+                            .map(|(secs, r)| r.started_at + chrono::Duration::seconds(secs.into())),
+                        stop_signal: Arc::new(Mutex::new(None)),
+                        signal_via: self.envs.signal_command(&task),
+                        // Still stopped or frozen; resuming works as before.
+                        pause: Arc::new(if task.status == TaskStatus::Paused { PauseClock::paused() } else { PauseClock::default() }),
+                        cgroup: run_id.as_deref().and_then(|r| self.cgroups.as_ref()?.find(r)),
+                    };
+                    let gpus = task.gpu_devices.as_deref().map(|d| self.gpus.claim(d));
+                    self.adopt(id, run_id, start, adopted, gpus).await;
+                }
+                _ => {
+                    tracing::warn!("Task {} was running before the restart and is gone", id);
+                    self.record_unobserved_exit(&id, run_id.as_deref(), TaskStatus::Lost, None, Some("gone after a server restart")).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Track a surviving process until it exits. Without being its parent
+    /// we can only poll for it to disappear. Its GPUs stay reserved until
+    /// then.
+    async fn adopt(self: &Arc<Self>, id: String, run_id: Option<String>, start: i64, adopted: AdoptedTask, gpus: Option<GpuReservation>) {
+        let (pid, deadline, stop_signal, pause) = (adopted.pid, adopted.deadline, adopted.stop_signal.clone(), adopted.pause.clone());
+        self.adopted.write().await.insert(id.clone(), adopted);
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(ADOPTED_POLL);
+            let mut timed_out = false;
+            while process_start_time(pid) == Some(start) {
+                poll.tick().await;
+                // Time paused since the restart doesn't count, as for `Attempt`.
+                let expired = deadline.is_some_and(|d| Utc::now() - pause.total() >= d);
+                if timed_out || !expired || pause.is_paused() || stop_signal.lock().unwrap().is_some() {
+                    continue;
+                }
+                let Some(process) = manager.live_process(&id).await else { continue };
+                tracing::warn!("Adopted task {} hit its timeout, stopping it", id);
+                timed_out = true;
+                if let Err(e) = manager.terminate(&id, process, manager.stop_grace).await {
+                    tracing::error!("{:?}", e);
+                }
+            }
+            drop(gpus);
+
+            let stopped_by = *stop_signal.lock().unwrap();
+            let (status, signal, reason) = match stopped_by {
+                Some(sig) if timed_out => (TaskStatus::TimedOut, Some(signals::signal_name(sig)), None),
+                Some(sig) => (TaskStatus::Stopped, Some(signals::signal_name(sig)), None),
+                None => (TaskStatus::Lost, None, Some("exited after a server restart; exit status unknown")),
+            };
+            manager.adopted.write().await.remove(&id);
+
+            tracing::info!("Adopted task {} finished: {:?}", id, status);
+            if let Err(e) = manager.record_unobserved_exit(&id, run_id.as_deref(), status, signal, reason).await {
+                tracing::error!("Failed to record exit of adopted task {}: {:?}", id, e);
+            }
+        });
+    }
+
+    async fn record_unobserved_exit(&self, id: &str, run_id: Option<&str>, status: TaskStatus, signal: Option<String>, reason: Option<&str>) -> Result<()> {
+        if let Some(run_id) = run_id {
+            sqlx::query("UPDATE runs SET status = ?, signal = ?, reason = ?, ended_at = datetime('now') WHERE id = ?")
+                .bind(status)
+                .bind(&signal)
+                .bind(reason)
+                .bind(run_id)
+                .execute(&self.pool)
+                .await?;
+        }
+        sqlx::query("UPDATE tasks SET status = ?, signal = ?, reason = ?, ended_at = datetime('now') WHERE id = ?")
+            .bind(status)
+            .bind(&signal)
+            .bind(reason)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        self.publish(id, status);
+        Ok(())
+    }
+}
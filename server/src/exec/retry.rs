@@ -0,0 +1,93 @@
+use std::time::Duration;
+use anyhow::Result;
+use rand::Rng;
+use crate::core::models::{Backoff, RetryPolicy};
+use super::signals::{parse_signal, signal_name};
+
+/// Rewrites the policy's signals to their canonical names, so "KILL" or "9"
+/// is stored as "SIGKILL". Fails on a signal that doesn't exist.
+pub fn normalize(policy: &mut RetryPolicy) -> Result<()> {
+    for s in &mut policy.signals {
+        *s = signal_name(parse_signal(s)?);
+    }
+    Ok(())
+}
+
+/// Whether a failed attempt that ended this way should be retried. `signal`
+/// is a name like "SIGKILL".
+pub fn is_retryable(policy: &RetryPolicy, exit_code: Option<i32>, signal: Option<&str>) -> bool {
+    match (exit_code, signal) {
+        // Compared by number, for policies stored before they were normalized.
+        (_, Some(sig)) => {
+            let sig = parse_signal(sig).ok();
+            sig.is_some() && policy.signals.iter().any(|s| parse_signal(s).ok() == sig)
+        }
+        (Some(code), None) => policy.exit_codes.is_empty() || policy.exit_codes.contains(&code),
+        (None, None) => false,
+    }
+}
+
+/// Delay before retry number `retry` (1 for the first retry).
+pub fn backoff_delay(policy: &RetryPolicy, retry: u32) -> Duration {
+    let base = policy.delay_secs as f64;
+    let mut secs = match policy.backoff {
+        Backoff::Fixed => base,
+        Backoff::Exponential => base * 2f64.powi(retry.saturating_sub(1) as i32),
+    };
+    if let Some(max) = policy.max_delay_secs {
+        secs = secs.min(max as f64);
+    }
+    // Spread retries over the upper half of the window so a batch of jobs
+    // failing together doesn't come back together.
+    if policy.jitter && secs > 0.0 {
+        secs = rand::thread_rng().gen_range(secs / 2.0..=secs);
+    }
+    Duration::from_secs_f64(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(signals: &[&str]) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            backoff: Backoff::Fixed,
+            delay_secs: 10,
+            max_delay_secs: None,
+            jitter: false,
+            exit_codes: vec![],
+            signals: signals.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn normalizes_signal_names() {
+        let mut p = policy(&["KILL", "9", "sigterm", " SIGINT "]);
+        normalize(&mut p).unwrap();
+        assert_eq!(p.signals, ["SIGKILL", "SIGKILL", "SIGTERM", "SIGINT"]);
+        assert!(normalize(&mut policy(&["SIGKIL"])).is_err());
+        assert!(normalize(&mut policy(&["999"])).is_err());
+    }
+
+    #[test]
+    fn matches_signals_by_number() {
+        let mut p = policy(&["KILL"]);
+        normalize(&mut p).unwrap();
+        assert!(is_retryable(&p, None, Some("SIGKILL")));
+        // Policies stored as given before normalization still match.
+        assert!(is_retryable(&policy(&["kill"]), None, Some("SIGKILL")));
+        assert!(is_retryable(&policy(&["9"]), None, Some("SIGKILL")));
+        assert!(!is_retryable(&policy(&["9"]), None, Some("SIGTERM")));
+        assert!(!is_retryable(&policy(&["bogus"]), None, Some("SIGKILL")));
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert!(is_retryable(&policy(&[]), Some(1), None));
+        let p = RetryPolicy { exit_codes: vec![75], ..policy(&[]) };
+        assert!(is_retryable(&p, Some(75), None));
+        assert!(!is_retryable(&p, Some(1), None));
+        assert!(!is_retryable(&p, None, None));
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+use anyhow::Result;
+use crate::core::models::{DependencyCondition, Task, TaskStatus};
+use crate::monitor::{MetricsSource, SystemMetrics};
+use super::{AlreadyRunning, TaskManager};
+
+pub const DEFAULT_QUEUE: &str = "default";
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Upper bound on running tasks across all queues. Tasks started by hand
+    /// count towards it but are never refused.
+    pub max_concurrent: usize,
+    /// Per-queue limits; queues not listed are only bound by `max_concurrent`.
+    pub queue_limits: HashMap<String, usize>,
+    /// Fallback poll interval. Task events wake the loop sooner.
+    pub tick: Duration,
+    /// How long a freshly started task's declared memory is held back from
+    /// the observed headroom, since it takes a while to show up in metrics.
+    pub settle: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            queue_limits: HashMap::new(),
+            tick: Duration::from_secs(5),
+            settle: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// Parses `QUEUE_LIMITS` style specs: `gpu=2,cpu=8`.
+    pub fn parse_queue_limits(spec: &str) -> Result<HashMap<String, usize>> {
+        let mut limits = HashMap::new();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (queue, limit) = part.split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected queue=limit, got {}", part))?;
+            limits.insert(queue.trim().to_string(), limit.trim().parse()?);
+        }
+        Ok(limits)
+    }
+}
+
+/// Admits `Queued` tasks into `TaskManager` in priority order, oldest first
+/// within a priority, while the concurrency limits allow. `Retrying` tasks
+/// whose backoff is over are admitted the same way, so a retry never goes
+/// past a limit the first attempt had to respect.
+///
+/// All queue state lives in the `tasks` table, so anything queued before a
+/// restart is picked up again on the first pass.
+///
+/// Tasks with resource constraints are held back until the latest metrics
+/// show enough headroom, and tasks that want GPUs until enough devices are
+/// free. Those that don't fit are skipped for the pass, so a smaller task
+/// behind them may start first.
+pub struct Scheduler {
+    manager: Arc<TaskManager>,
+    pool: SqlitePool,
+    config: SchedulerConfig,
+    metrics: Arc<dyn MetricsSource>,
+    reserved: Vec<Reservation>,
+}
+
+struct Reservation {
+    at: Instant,
+    mem_mb: i64,
+    gpu_mem_mb: i64,
+}
+
+/// Whether `metrics` leave room for `task` once `reserved` memory (in MB) is
+/// taken off. Constrained tasks never start without metrics.
+fn has_headroom(task: &Task, metrics: Option<&SystemMetrics>, reserved_mem_mb: i64, reserved_gpu_mem_mb: i64) -> bool {
+    if task.min_free_mem_mb.is_none() && task.max_cpu_percent.is_none() && task.min_free_gpu_mem_mb.is_none() {
+        return true;
+    }
+    let Some(m) = metrics else { return false };
+
+    if let Some(need) = task.min_free_mem_mb {
+        let free_mb = (m.mem_total.saturating_sub(m.mem_used) / (1024 * 1024)) as i64;
+        if free_mb - reserved_mem_mb < need {
+            return false;
+        }
+    }
+    if let Some(max) = task.max_cpu_percent {
+        if m.cpu > max {
+            return false;
+        }
+    }
+    // With whole GPUs requested the allocator checks each device instead.
+    if let Some(need) = task.min_free_gpu_mem_mb.filter(|_| task.gpus.is_none_or(|n| n == 0)) {
+        // nvidia-smi reports MiB already. Any one device with room will do.
+        if !m.gpus.iter().any(|gpu| gpu.mem_total.saturating_sub(gpu.mem_used) as i64 - reserved_gpu_mem_mb >= need) {
+            return false;
+        }
+    }
+    true
+}
+
+enum Readiness {
+    Ready,
+    Waiting,
+    /// An upstream task finished in a way the condition rules out.
+    Blocked,
+}
+
+impl Scheduler {
+    pub fn new(manager: Arc<TaskManager>, pool: SqlitePool, config: SchedulerConfig, metrics: Arc<dyn MetricsSource>) -> Self {
+        Self { manager, pool, config, metrics, reserved: Vec::new() }
+    }
+
+    pub async fn run(mut self) {
+        let mut events = self.manager.subscribe();
+        let mut interval = tokio::time::interval(self.config.tick);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                ev = events.recv() => match ev {
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+
+            if let Err(e) = self.admit().await {
+                tracing::error!("Scheduler pass failed: {:?}", e);
+            }
+        }
+    }
+
+    async fn admit(&mut self) -> Result<()> {
+        // Before the concurrency check, so dependents are skipped even while
+        // every slot is taken.
+        self.skip_blocked().await?;
+
+        let running: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT queue, COUNT(*) FROM tasks WHERE status = 'Running' GROUP BY queue"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut per_queue: HashMap<String, usize> = running.into_iter()
+            .map(|(q, n)| (q, n as usize))
+            .collect();
+        let mut total: usize = per_queue.values().sum();
+        if total >= self.config.max_concurrent {
+            return Ok(());
+        }
+
+        let queued: Vec<Task> = sqlx::query_as(
+            "SELECT * FROM tasks WHERE status = 'Queued' OR (status = 'Retrying' AND (retry_at IS NULL OR retry_at <= datetime('now'))) \
+             ORDER BY priority DESC, CASE WHEN status = 'Retrying' THEN retry_at ELSE queued_at END ASC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let settle = self.config.settle;
+        self.reserved.retain(|r| r.at.elapsed() < settle);
+        let metrics = self.metrics.latest();
+
+        for task in queued {
+            if total >= self.config.max_concurrent {
+                break;
+            }
+            match self.readiness(&task.id).await? {
+                Readiness::Ready => {}
+                Readiness::Waiting => continue,
+                Readiness::Blocked => {
+                    tracing::info!("Skipping task {}: dependency not met", task.id);
+                    self.manager.mark_finished(&task.id, TaskStatus::Skipped).await?;
+                    continue;
+                }
+            }
+
+            let in_queue = per_queue.get(&task.queue).copied().unwrap_or(0);
+            if let Some(limit) = self.config.queue_limits.get(&task.queue) {
+                if in_queue >= *limit {
+                    continue;
+                }
+            }
+
+            let reserved_mem: i64 = self.reserved.iter().map(|r| r.mem_mb).sum();
+            let reserved_gpu: i64 = self.reserved.iter().map(|r| r.gpu_mem_mb).sum();
+            if !has_headroom(&task, metrics.as_ref(), reserved_mem, reserved_gpu) {
+                tracing::debug!("Task {} waiting for resources", task.id);
+                continue;
+            }
+            if let Some(n) = task.gpus.filter(|n| *n > 0) {
+                if !self.manager.gpus().available(n, task.min_free_gpu_mem_mb) {
+                    tracing::debug!("Task {} waiting for {} GPUs", task.id, n);
+                    continue;
+                }
+            }
+
+            tracing::info!("Admitting task {} from queue {}", task.id, task.queue);
+            let started = match task.status {
+                TaskStatus::Retrying => self.manager.spawn_retry(&task.id).await,
+                _ => self.manager.spawn(&task.id).await.map(|_| true),
+            };
+            match started {
+                Ok(true) => {
+                    total += 1;
+                    *per_queue.entry(task.queue.clone()).or_insert(0) += 1;
+                    if task.min_free_mem_mb.is_some() || task.min_free_gpu_mem_mb.is_some() {
+                        self.reserved.push(Reservation {
+                            at: Instant::now(),
+                            mem_mb: task.min_free_mem_mb.unwrap_or(0),
+                            gpu_mem_mb: task.min_free_gpu_mem_mb.unwrap_or(0),
+                        });
+                    }
+                }
+                Ok(false) => {}
+                // Started by hand since the query above; that run stands.
+                Err(e) if e.is::<AlreadyRunning>() => {
+                    tracing::debug!("Task {} was started elsewhere", task.id);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to start queued task {}: {:?}", task.id, e);
+                    self.manager.mark_finished(&task.id, TaskStatus::Failed).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks queued tasks whose dependencies can no longer be met as
+    /// `Skipped`, repeating until nothing changes so a whole chain of
+    /// dependents is skipped in one pass.
+    async fn skip_blocked(&self) -> Result<()> {
+        loop {
+            let deps: Vec<(String, DependencyCondition, TaskStatus)> = sqlx::query_as(
+                "SELECT d.task_id, d.condition, t.status FROM task_dependencies d \
+                 JOIN tasks q ON q.id = d.task_id JOIN tasks t ON t.id = d.depends_on \
+                 WHERE q.status = 'Queued'"
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut blocked: Vec<String> = deps.into_iter()
+                .filter(|(_, condition, upstream)| condition.satisfied_by(*upstream) == Some(false))
+                .map(|(id, _, _)| id)
+                .collect();
+            blocked.sort();
+            blocked.dedup();
+            if blocked.is_empty() {
+                return Ok(());
+            }
+            for id in blocked {
+                tracing::info!("Skipping task {}: dependency not met", id);
+                self.manager.mark_finished(&id, TaskStatus::Skipped).await?;
+            }
+        }
+    }
+
+    async fn readiness(&self, id: &str) -> Result<Readiness> {
+        let deps: Vec<(DependencyCondition, TaskStatus)> = sqlx::query_as(
+            "SELECT d.condition, t.status FROM task_dependencies d JOIN tasks t ON t.id = d.depends_on WHERE d.task_id = ?"
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut readiness = Readiness::Ready;
+        for (condition, upstream) in deps {
+            match condition.satisfied_by(upstream) {
+                Some(true) => {}
+                Some(false) => return Ok(Readiness::Blocked),
+                None => readiness = Readiness::Waiting,
+            }
+        }
+        Ok(readiness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::models::test_task as task;
+    use crate::monitor::GpuMetrics;
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn gpu(index: u32, mem_used: u64, mem_total: u64) -> GpuMetrics {
+        GpuMetrics {
+            index,
+            uuid: format!("GPU-{}", index),
+            name: "Test GPU".into(),
+            util: 0,
+            mem_used,
+            mem_total,
+            temperature: None,
+            power_draw: None,
+            processes: Vec::new(),
+        }
+    }
+
+    /// 16 GB of RAM with 4 GB used, 50% CPU, and two 16 GB GPUs with 2 GB
+    /// and 12 GB free.
+    fn metrics() -> SystemMetrics {
+        SystemMetrics {
+            cpu: 50.0,
+            mem_used: 4096 * MB,
+            mem_total: 16384 * MB,
+            gpus: vec![gpu(0, 14336, 16384), gpu(1, 4096, 16384)],
+        }
+    }
+
+    #[test]
+    fn unconstrained_tasks_always_fit() {
+        assert!(has_headroom(&task(), None, 0, 0));
+        assert!(has_headroom(&task(), Some(&metrics()), i64::MAX, i64::MAX));
+    }
+
+    #[test]
+    fn constrained_tasks_wait_for_metrics() {
+        let t = Task { max_cpu_percent: Some(100.0), ..task() };
+        assert!(!has_headroom(&t, None, 0, 0));
+    }
+
+    #[test]
+    fn memory() {
+        let m = metrics();
+        assert!(has_headroom(&Task { min_free_mem_mb: Some(12288), ..task() }, Some(&m), 0, 0));
+        assert!(!has_headroom(&Task { min_free_mem_mb: Some(12289), ..task() }, Some(&m), 0, 0));
+        // Memory promised to tasks that started recently is already spoken for.
+        assert!(has_headroom(&Task { min_free_mem_mb: Some(8192), ..task() }, Some(&m), 4096, 0));
+        assert!(!has_headroom(&Task { min_free_mem_mb: Some(8192), ..task() }, Some(&m), 4097, 0));
+    }
+
+    #[test]
+    fn cpu() {
+        let m = metrics();
+        assert!(has_headroom(&Task { max_cpu_percent: Some(50.0), ..task() }, Some(&m), 0, 0));
+        assert!(!has_headroom(&Task { max_cpu_percent: Some(49.9), ..task() }, Some(&m), 0, 0));
+    }
+
+    #[test]
+    fn gpu_memory_on_any_device() {
+        let m = metrics();
+        assert!(has_headroom(&Task { min_free_gpu_mem_mb: Some(12288), ..task() }, Some(&m), 0, 0));
+        assert!(!has_headroom(&Task { min_free_gpu_mem_mb: Some(12289), ..task() }, Some(&m), 0, 0));
+        assert!(has_headroom(&Task { min_free_gpu_mem_mb: Some(8192), ..task() }, Some(&m), 0, 4096));
+        assert!(!has_headroom(&Task { min_free_gpu_mem_mb: Some(8192), ..task() }, Some(&m), 0, 4097));
+
+        let no_gpus = SystemMetrics { gpus: Vec::new(), ..metrics() };
+        assert!(!has_headroom(&Task { min_free_gpu_mem_mb: Some(1), ..task() }, Some(&no_gpus), 0, 0));
+    }
+
+    #[test]
+    fn whole_gpus_leave_memory_to_the_allocator() {
+        let m = metrics();
+        let t = Task { gpus: Some(1), min_free_gpu_mem_mb: Some(65536), ..task() };
+        assert!(has_headroom(&t, Some(&m), 0, 0));
+        // Asking for zero devices is the same as not asking.
+        let t = Task { gpus: Some(0), ..t };
+        assert!(!has_headroom(&t, Some(&m), 0, 0));
+    }
+
+    #[test]
+    fn every_constraint_must_hold() {
+        let m = metrics();
+        let t = Task { min_free_mem_mb: Some(1024), max_cpu_percent: Some(90.0), min_free_gpu_mem_mb: Some(1024), ..task() };
+        assert!(has_headroom(&t, Some(&m), 0, 0));
+        assert!(!has_headroom(&Task { max_cpu_percent: Some(10.0), ..t.clone() }, Some(&m), 0, 0));
+        assert!(!has_headroom(&Task { min_free_mem_mb: Some(20000), ..t }, Some(&m), 0, 0));
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Result, anyhow, Context};
+
+const SIGNALS: &[(&str, i32)] = &[
+    ("SIGHUP", libc::SIGHUP),
+    ("SIGINT", libc::SIGINT),
+    ("SIGQUIT", libc::SIGQUIT),
+    ("SIGABRT", libc::SIGABRT),
+    ("SIGBUS", libc::SIGBUS),
+    ("SIGFPE", libc::SIGFPE),
+    ("SIGKILL", libc::SIGKILL),
+    ("SIGUSR1", libc::SIGUSR1),
+    ("SIGSEGV", libc::SIGSEGV),
+    ("SIGUSR2", libc::SIGUSR2),
+    ("SIGPIPE", libc::SIGPIPE),
+    ("SIGALRM", libc::SIGALRM),
+    ("SIGTERM", libc::SIGTERM),
+    ("SIGXCPU", libc::SIGXCPU),
+    ("SIGCONT", libc::SIGCONT),
+    ("SIGSTOP", libc::SIGSTOP),
+    ("SIGTSTP", libc::SIGTSTP),
+    ("SIGWINCH", libc::SIGWINCH),
+];
+
+/// Accepts "SIGTERM", "TERM" or a plain number like "15".
+pub fn parse_signal(s: &str) -> Result<i32> {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<i32>() {
+        return match SIGNALS.iter().find(|(_, num)| *num == n) {
+            Some((_, num)) => Ok(*num),
+            None => Err(anyhow!("Unsupported signal number {}", n)),
+        };
+    }
+    let upper = s.to_ascii_uppercase();
+    let name = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+    SIGNALS.iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, num)| *num)
+        .ok_or_else(|| anyhow!("Unknown signal {}", s))
+}
+
+pub fn signal_name(sig: i32) -> String {
+    SIGNALS.iter()
+        .find(|(_, num)| *num == sig)
+        .map(|(n, _)| n.to_string())
+        .unwrap_or_else(|| format!("SIG{}", sig))
+}
+
+/// Maps a `strsignal` description such as "Killed", which is all
+/// portable-pty reports for a signal death, back to its number.
+pub fn from_description(desc: &str) -> Option<i32> {
+    SIGNALS.iter().map(|(_, num)| *num).find(|num| {
+        let s = unsafe { libc::strsignal(*num) };
+        !s.is_null() && unsafe { std::ffi::CStr::from_ptr(s) }.to_string_lossy() == desc
+    })
+}
+
+/// Signal every process in the group led by `pid`.
+///
+/// portable-pty calls `setsid` in the child, so the task's pid is also its
+/// process group id and `sh -c` grandchildren inherit it.
+/// A group that is already gone is not an error.
+pub fn kill_group(pid: u32, sig: i32) -> Result<()> {
+    let ret = unsafe { libc::kill(-(pid as libc::pid_t), sig) };
+    if ret == -1 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(anyhow!("Failed to send {} to process group {}: {}", signal_name(sig), pid, err));
+        }
+    }
+    Ok(())
+}
+
+/// Run an environment's signal command (see
+/// `EnvironmentBackend::signal_command`) for `sig`.
+pub async fn run_signal_command(command: &[String], sig: i32) -> Result<()> {
+    let name = signal_name(sig);
+    let argv: Vec<String> = command.iter().map(|a| a.replace("{signal}", &name)).collect();
+    let (program, args) = argv.split_first().ok_or_else(|| anyhow!("Empty signal command"))?;
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!("Failed to send {}: {}", name, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
@@ -1,5 +1,4 @@
 use serde::{Serialize, Deserialize};
-use std::path::{Path, PathBuf};
 use std::fs;
 use std::time::SystemTime;
 
@@ -1,10 +1,9 @@
-use axum::Router;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use std::path::PathBuf;
-use tokio::sync::broadcast;
+use std::time::Duration;
 
 mod api;
 mod core;
@@ -12,14 +11,30 @@ mod db;
 mod exec;
 mod fs;
 mod monitor;
+mod schedules;
+mod secrets;
 
 use crate::db::init::init_db;
-use crate::exec::TaskManager;
+use crate::exec::{TaskManager, DEFAULT_STOP_GRACE};
+use crate::exec::envs::EnvRegistry;
+use crate::exec::gpus::{GpuInventory, StaticInventory};
+use crate::exec::limits::Cgroups;
+use crate::exec::scheduler::{Scheduler, SchedulerConfig};
 use crate::api::{AppState, app_router};
-use crate::monitor::{Monitor};
+use crate::monitor::{LatestMetrics, Monitor};
+use crate::schedules::ScheduleRunner;
+use crate::secrets::SecretStore;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Tasks with resource limits are started through this binary.
+    if std::env::args().nth(1).as_deref() == Some(exec::limits::LAUNCH_ARG) {
+        exec::limits::launch();
+    }
+    serve()
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn serve() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
@@ -33,19 +48,73 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let log_dir = PathBuf::from("data/logs");
     std::fs::create_dir_all(&log_dir)?;
 
-    let task_manager = Arc::new(TaskManager::new(pool.clone(), log_dir));
-    
-    // Start Monitor
-    let (monitor, rx) = Monitor::new();
+    let stop_grace = std::env::var("STOP_GRACE_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_STOP_GRACE);
+
+    let key_file = std::env::var("SECRETS_KEY_FILE").unwrap_or_else(|_| "data/secrets.key".to_string());
+    let secrets = Arc::new(SecretStore::open(pool.clone(), std::path::Path::new(&key_file))?);
+
+    let backends_file = std::env::var("ENV_BACKENDS_FILE").unwrap_or_else(|_| "data/backends.toml".to_string());
+    // Where discovery looks for venvs and uv projects; colon separated.
+    let search_roots: Vec<PathBuf> = match std::env::var_os("ENV_SEARCH_ROOTS") {
+        Some(roots) => std::env::split_paths(&roots).collect(),
+        None => std::env::var_os("HOME").map(PathBuf::from).into_iter().collect(),
+    };
+    // docker, podman, or anything else with the same CLI.
+    let container_runtime = std::env::var("CONTAINER_RUNTIME").unwrap_or_else(|_| "docker".to_string());
+    let envs = EnvRegistry::builtin(&search_roots, &container_runtime).load_file(std::path::Path::new(&backends_file))?;
+
+    let (monitor, _rx) = Monitor::new();
     let tx = monitor.tx.clone(); // If we want to access it, but actually Monitor holds it.
-    // We just run monitor
+    let metrics = Arc::new(LatestMetrics::follow(&tx));
+
+    // A JSON device list to allocate from instead of what the monitor sees.
+    let gpu_inventory: Arc<dyn GpuInventory> = match std::env::var("GPU_INVENTORY") {
+        Ok(path) => Arc::new(StaticInventory::load(std::path::Path::new(&path))?),
+        Err(_) => metrics.clone(),
+    };
+
+    let task_manager = Arc::new(
+        TaskManager::new(pool.clone(), log_dir)
+            .with_stop_grace(stop_grace)
+            .with_secrets(secrets.clone())
+            .with_envs(envs)
+            .with_cgroups(Cgroups::init())
+            .with_gpus(gpu_inventory)
+    );
+    task_manager.recover().await?;
+
+    // Start Monitor
+    let monitor = monitor.with_tasks(task_manager.clone());
     tokio::spawn(async move {
         monitor.run().await;
     });
+    
+    let mut sched_config = SchedulerConfig::default();
+    if let Some(n) = std::env::var("MAX_CONCURRENT_TASKS").ok().and_then(|s| s.parse().ok()) {
+        sched_config.max_concurrent = n;
+    }
+    if let Ok(spec) = std::env::var("QUEUE_LIMITS") {
+        sched_config.queue_limits = SchedulerConfig::parse_queue_limits(&spec)?;
+    }
+
+    let scheduler = Scheduler::new(task_manager.clone(), pool.clone(), sched_config, metrics);
+    tokio::spawn(async move {
+        scheduler.run().await;
+    });
+
+    let schedule_runner = ScheduleRunner::new(task_manager.clone(), pool.clone());
+    tokio::spawn(async move {
+        schedule_runner.run().await;
+    });
 
     let state = Arc::new(AppState {
         task_manager,
         pool,
+        secrets,
         monitor_tx: tx,
     });
 
@@ -1,36 +1,95 @@
 use sysinfo::System;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::broadcast;
 use serde::Serialize;
 use tokio::process::Command;
+use crate::exec::TaskManager;
+
+mod nvidia;
 
 #[derive(Clone, Serialize, Debug)]
 pub struct SystemMetrics {
     pub cpu: f32,
     pub mem_used: u64,
     pub mem_total: u64,
-    pub gpu: Option<GpuMetrics>,
+    pub gpus: Vec<GpuMetrics>,
 }
 
+/// One device. Memory is in MiB, as nvidia-smi reports it.
 #[derive(Clone, Serialize, Debug)]
 pub struct GpuMetrics {
+    pub index: u32,
+    pub uuid: String,
     pub name: String,
     pub util: u32,
     pub mem_used: u64,
     pub mem_total: u64,
+    pub temperature: Option<u32>, // degrees C
+    pub power_draw: Option<f32>, // watts
+    pub processes: Vec<GpuProcess>,
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct GpuProcess {
+    pub pid: u32,
+    pub mem_used: u64,
+    pub task_id: Option<String>, // the task whose session the process is in
+}
+
+/// Where the scheduler gets its view of the machine. Kept behind a trait so
+/// admission can be driven by canned metrics instead of the live monitor.
+pub trait MetricsSource: Send + Sync {
+    fn latest(&self) -> Option<SystemMetrics>;
+}
+
+/// Remembers the most recent sample broadcast by `Monitor`.
+pub struct LatestMetrics {
+    latest: Arc<Mutex<Option<SystemMetrics>>>,
+}
+
+impl LatestMetrics {
+    pub fn follow(tx: &broadcast::Sender<SystemMetrics>) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let slot = latest.clone();
+        let mut rx = tx.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(m) => *slot.lock().unwrap() = Some(m),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        Self { latest }
+    }
+}
+
+impl MetricsSource for LatestMetrics {
+    fn latest(&self) -> Option<SystemMetrics> {
+        self.latest.lock().unwrap().clone()
+    }
 }
 
 pub struct Monitor {
     sys: Arc<Mutex<System>>,
     pub tx: broadcast::Sender<SystemMetrics>,
+    tasks: Option<Arc<TaskManager>>,
 }
 
 impl Monitor {
     pub fn new() -> (Self, broadcast::Receiver<SystemMetrics>) {
         let (tx, rx) = broadcast::channel(16); // Buffer size 16 is plenty for real-time stats
         let sys = Arc::new(Mutex::new(System::new_all()));
-        (Self { sys, tx }, rx)
+        (Self { sys, tx, tasks: None }, rx)
+    }
+
+    /// Where to look up which task a GPU process belongs to.
+    pub fn with_tasks(mut self, tasks: Arc<TaskManager>) -> Self {
+        self.tasks = Some(tasks);
+        self
     }
 
     pub async fn run(self) {
@@ -50,12 +109,12 @@ impl Monitor {
             
             let metrics = SystemMetrics {
                 cpu: cpu_global,
-                mem_used: mem_used,
-                mem_total: mem_total,
-                gpu: Monitor::sample_gpu().await,
+                mem_used,
+                mem_total,
+                gpus: self.sample_gpus().await,
             };
 
-            if let Err(_) = self.tx.send(metrics) {
+            if self.tx.send(metrics).is_err() {
                 break; // No listeners, but we keep running? Or stop? 
                 // Linus: "If nobody listens, does the tree make a sound? Who cares. Run loops are cheap, but why waste cycles?"
                 // Ideally we'd pause. But for now, keep robust.
@@ -63,29 +122,45 @@ impl Monitor {
         }
     }
 
-    async fn sample_gpu() -> Option<GpuMetrics> {
+    async fn sample_gpus(&self) -> Vec<GpuMetrics> {
         // nvidia-smi is slow and heavy. Don't block the executor.
         // Also it might not exist.
         // Don't panic.
-        let output = Command::new("nvidia-smi")
-            .args(&["--query-gpu=name,utilization.gpu,memory.used,memory.total", "--format=csv,noheader,nounits"])
-            .output()
-            .await
-            .ok()?;
+        let Some(out) = Self::nvidia_smi(&nvidia::GPU_QUERY).await else { return Vec::new() };
+        let mut gpus = nvidia::parse_gpus(&out);
+        if gpus.is_empty() {
+            return gpus;
+        }
 
+        let mut apps = Self::nvidia_smi(&nvidia::APPS_QUERY).await
+            .map(|out| nvidia::parse_compute_apps(&out))
+            .unwrap_or_default();
+        if let Some(tasks) = &self.tasks {
+            let leaders = tasks.task_pids().await;
+            for (_, process) in apps.iter_mut() {
+                process.task_id = owner(process.pid, &leaders);
+            }
+        }
+        nvidia::attach_processes(&mut gpus, apps);
+        gpus
+    }
+
+    async fn nvidia_smi(args: &[&str]) -> Option<String> {
+        let output = Command::new("nvidia-smi").args(args).output().await.ok()?;
         if !output.status.success() {
             return None;
         }
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
 
-        let out_str = String::from_utf8_lossy(&output.stdout);
-        let line = out_str.lines().next()?;
-        let mut cols = line.split(',').map(|s| s.trim());
-
-        Some(GpuMetrics {
-            name: cols.next()?.to_string(),
-            util: cols.next()?.parse().unwrap_or(0),
-            mem_used: cols.next()?.parse().unwrap_or(0),
-            mem_total: cols.next()?.parse().unwrap_or(0),
-        })
+/// The task `pid` belongs to. Each task leads its own session, so anything
+/// it started is found by session id, however deep. Processes in another
+/// pid namespace, like a container's, don't match.
+fn owner(pid: u32, leaders: &HashMap<u32, String>) -> Option<String> {
+    let sid = unsafe { libc::getsid(pid as libc::pid_t) };
+    if sid <= 0 {
+        return None;
     }
+    leaders.get(&(sid as u32)).cloned()
 }
@@ -0,0 +1,133 @@
+use std::str::FromStr;
+use super::{GpuMetrics, GpuProcess};
+
+/// Arguments for one line per device, in the order `parse_gpus` expects.
+pub const GPU_QUERY: [&str; 2] = [
+    "--query-gpu=index,uuid,name,utilization.gpu,memory.used,memory.total,temperature.gpu,power.draw",
+    "--format=csv,noheader,nounits",
+];
+
+/// Arguments for one line per compute process, for `parse_compute_apps`.
+pub const APPS_QUERY: [&str; 2] = [
+    "--query-compute-apps=gpu_uuid,pid,used_memory",
+    "--format=csv,noheader,nounits",
+];
+
+/// A numeric field, or `None` for `[N/A]`, `[Not Supported]` and the like.
+fn number<T: FromStr>(field: &str) -> Option<T> {
+    field.trim().parse().ok()
+}
+
+/// One `GpuMetrics` per line of `GPU_QUERY` output, without processes.
+/// Lines that don't parse are skipped rather than failing the sample.
+pub fn parse_gpus(output: &str) -> Vec<GpuMetrics> {
+    output.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 8 {
+            return None;
+        }
+        // Everything between the uuid and the numbers is the name, commas and all.
+        let (head, tail) = fields.split_at(fields.len() - 5);
+        Some(GpuMetrics {
+            index: number(head[0])?,
+            uuid: head[1].to_string(),
+            name: head[2..].join(", "),
+            util: number(tail[0]).unwrap_or(0),
+            mem_used: number(tail[1]).unwrap_or(0),
+            mem_total: number(tail[2]).unwrap_or(0),
+            temperature: number(tail[3]),
+            power_draw: number(tail[4]),
+            processes: Vec::new(),
+        })
+    }).collect()
+}
+
+/// `(gpu uuid, process)` per line of `APPS_QUERY` output. Memory nvidia-smi
+/// can't see (common inside containers) comes out as 0.
+pub fn parse_compute_apps(output: &str) -> Vec<(String, GpuProcess)> {
+    output.lines().filter_map(|line| {
+        let mut fields = line.split(',').map(str::trim);
+        let uuid = fields.next().filter(|u| !u.is_empty())?;
+        let pid = number(fields.next()?)?;
+        let mem_used = fields.next().and_then(number).unwrap_or(0);
+        Some((uuid.to_string(), GpuProcess { pid, mem_used, task_id: None }))
+    }).collect()
+}
+
+/// Hand each process to the device it runs on. Processes on devices not in
+/// `gpus` are dropped.
+pub fn attach_processes(gpus: &mut [GpuMetrics], apps: Vec<(String, GpuProcess)>) {
+    for (uuid, process) in apps {
+        if let Some(gpu) = gpus.iter_mut().find(|g| g.uuid == uuid) {
+            gpu.processes.push(process);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GPUS_8X: &str = include_str!("fixtures/gpus-8x-a100.csv");
+    const APPS_8X: &str = include_str!("fixtures/compute-apps-8x-a100.csv");
+    const GPUS_MIXED: &str = include_str!("fixtures/gpus-mixed.csv");
+
+    #[test]
+    fn parses_every_device() {
+        let gpus = parse_gpus(GPUS_8X);
+        assert_eq!(gpus.len(), 8);
+        assert_eq!(gpus.iter().map(|g| g.index).collect::<Vec<_>>(), (0..8).collect::<Vec<_>>());
+
+        let last = &gpus[7];
+        assert_eq!(last.uuid, "GPU-7e9a1c3d-5f7b-4d0e-9a2c-4b6d8f0a2c47");
+        assert_eq!(last.name, "NVIDIA A100-SXM4-80GB");
+        assert_eq!((last.util, last.mem_used, last.mem_total), (0, 4, 81920));
+        assert_eq!(last.temperature, Some(32));
+        assert_eq!(last.power_draw, Some(62.18));
+    }
+
+    #[test]
+    fn unsupported_fields_are_missing_not_fatal() {
+        let gpus = parse_gpus(GPUS_MIXED);
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].power_draw, Some(27.31));
+
+        let gtx = &gpus[1];
+        assert_eq!(gtx.name, "NVIDIA GeForce GTX 1080");
+        assert_eq!(gtx.util, 0);
+        assert_eq!((gtx.mem_used, gtx.mem_total), (310, 8192));
+        assert_eq!(gtx.temperature, Some(39));
+        assert_eq!(gtx.power_draw, None);
+    }
+
+    #[test]
+    fn keeps_commas_in_names_and_skips_garbage() {
+        let out = "0, GPU-a, Weird, Name, 5, 10, 20, 40, 50.5\nNo devices were found\n\n";
+        let gpus = parse_gpus(out);
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].name, "Weird, Name");
+        assert_eq!(gpus[0].mem_total, 20);
+    }
+
+    #[test]
+    fn attaches_processes_to_their_devices() {
+        let apps = parse_compute_apps(APPS_8X);
+        assert_eq!(apps.len(), 5);
+        // Memory the driver won't report.
+        assert_eq!(apps[4].1.mem_used, 0);
+
+        let mut gpus = parse_gpus(GPUS_8X);
+        attach_processes(&mut gpus, apps);
+        let per_device: Vec<usize> = gpus.iter().map(|g| g.processes.len()).collect();
+        assert_eq!(per_device, [1, 1, 0, 0, 2, 0, 1, 0]);
+
+        let pids: Vec<(u32, u64)> = gpus[4].processes.iter().map(|p| (p.pid, p.mem_used)).collect();
+        assert_eq!(pids, [(51007, 10204), (51044, 10198)]);
+    }
+
+    #[test]
+    fn no_processes() {
+        assert!(parse_compute_apps("").is_empty());
+        assert!(parse_compute_apps("\n").is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Local, Timelike, Utc};
+use croner::Cron;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+use anyhow::{Result, anyhow, Context};
+use crate::core::models::{CatchUpPolicy, OverlapPolicy, Schedule, ScheduleRequest, Task, TaskStatus};
+use crate::db::tasks::insert_task;
+use crate::exec::TaskManager;
+
+/// A due time this far in the past counts as missed rather than late.
+const MISSED_GRACE: chrono::Duration = chrono::Duration::seconds(60);
+
+pub fn validate(req: &ScheduleRequest) -> Result<()> {
+    match (&req.cron, req.interval_secs) {
+        (Some(expr), None) => {
+            Cron::from_str(expr).map_err(|e| anyhow!("Invalid cron expression: {}", e))?;
+        }
+        (None, Some(secs)) if secs > 0 => {}
+        (None, Some(_)) => return Err(anyhow!("interval_secs must be positive")),
+        _ => return Err(anyhow!("Exactly one of cron or interval_secs is required")),
+    }
+    Ok(())
+}
+
+/// First due time strictly after `after`. Cron expressions are evaluated in
+/// the server's local time zone, like crontab.
+pub fn next_occurrence(cron: Option<&str>, interval_secs: Option<i64>, after: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Some(expr) = cron {
+        let cron = Cron::from_str(expr).map_err(|e| anyhow!("Invalid cron expression: {}", e))?;
+        // croner carries sub-second precision through to the result
+        let after = after.with_nanosecond(0).unwrap_or(after);
+        let next = cron.find_next_occurrence(&after.with_timezone(&Local), false)
+            .map_err(|e| anyhow!("No next occurrence for {}: {}", expr, e))?;
+        return Ok(next.with_timezone(&Utc));
+    }
+    let secs = interval_secs.ok_or_else(|| anyhow!("Schedule has neither cron nor interval"))?;
+    Ok(after + chrono::Duration::seconds(secs))
+}
+
+/// The first due time after `due` that is also after `now`, keeping to the
+/// schedule's own grid so intervals don't drift by however late the check
+/// ran. Any due times skipped over here were missed along with `due`, and
+/// the one run (or skip) for `due` stands in for all of them.
+pub fn following_occurrence(cron: Option<&str>, interval_secs: Option<i64>, due: DateTime<Utc>, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let (None, Some(secs)) = (cron, interval_secs.filter(|s| *s > 0)) {
+        let behind = (now - due).num_seconds().max(0);
+        return Ok(due + chrono::Duration::seconds((behind / secs + 1) * secs));
+    }
+    let mut next = next_occurrence(cron, interval_secs, due)?;
+    while next <= now {
+        next = next_occurrence(cron, interval_secs, next)?;
+    }
+    Ok(next)
+}
+
+/// Starts runs of template tasks when their schedules come due.
+///
+/// Each run is a fresh copy of the template task, handed to the scheduler
+/// through `TaskManager::enqueue` so queue limits still apply.
+pub struct ScheduleRunner {
+    manager: Arc<TaskManager>,
+    pool: SqlitePool,
+    tick: Duration,
+}
+
+impl ScheduleRunner {
+    pub fn new(manager: Arc<TaskManager>, pool: SqlitePool) -> Self {
+        Self { manager, pool, tick: Duration::from_secs(5) }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.tick);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.check().await {
+                tracing::error!("Schedule pass failed: {:?}", e);
+            }
+        }
+    }
+
+    async fn check(&self) -> Result<()> {
+        let schedules: Vec<Schedule> = sqlx::query_as("SELECT * FROM schedules WHERE enabled = 1")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let now = Utc::now();
+        for s in schedules {
+            let Some(due) = s.next_run_at else { continue };
+            if due > now {
+                continue;
+            }
+
+            let missed = now - due > MISSED_GRACE;
+            let fire = if missed && s.catch_up == CatchUpPolicy::Skip {
+                tracing::info!("Schedule {} missed its run at {}, skipping", s.id, due);
+                false
+            } else if s.overlap == OverlapPolicy::Skip && self.previous_active(&s).await? {
+                tracing::info!("Schedule {} skipped: previous run still active", s.id);
+                false
+            } else {
+                true
+            };
+
+            let mut last_task_id = s.last_task_id.clone();
+            let mut last_run_at = s.last_run_at;
+            if fire {
+                match self.fire(&s).await {
+                    Ok(task_id) => {
+                        last_task_id = Some(task_id);
+                        last_run_at = Some(now);
+                    }
+                    Err(e) => tracing::error!("Schedule {} failed to start a run: {:?}", s.id, e),
+                }
+            }
+
+            let next = following_occurrence(s.cron.as_deref(), s.interval_secs, due, now)?;
+            sqlx::query("UPDATE schedules SET next_run_at = ?, last_run_at = ?, last_task_id = ? WHERE id = ?")
+                .bind(next)
+                .bind(last_run_at)
+                .bind(&last_task_id)
+                .bind(&s.id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn previous_active(&self, s: &Schedule) -> Result<bool> {
+        let Some(prev) = &s.last_task_id else { return Ok(false) };
+        let status: Option<(TaskStatus,)> = sqlx::query_as("SELECT status FROM tasks WHERE id = ?")
+            .bind(prev)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(matches!(status, Some((TaskStatus::Queued | TaskStatus::Running | TaskStatus::Paused | TaskStatus::Retrying,))))
+    }
+
+    async fn fire(&self, s: &Schedule) -> Result<String> {
+        let template: Task = sqlx::query_as("SELECT * FROM tasks WHERE id = ?")
+            .bind(&s.task_id)
+            .fetch_one(&self.pool)
+            .await
+            .context("Template task not found")?;
+
+        let mut run = template.clone();
+        run.id = Uuid::new_v4().to_string();
+        run.status = TaskStatus::Pending;
+        run.created_at = Utc::now();
+        run.last_run_id = None;
+
+        let mut conn = self.pool.acquire().await?;
+        insert_task(&mut conn, &run).await?;
+        drop(conn);
+
+        self.manager.enqueue(&run.id).await?;
+        tracing::info!("Schedule {} started task {}", s.id, run.id);
+        Ok(run.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    fn request(cron: Option<&str>, interval_secs: Option<i64>) -> ScheduleRequest {
+        ScheduleRequest {
+            name: "s".into(),
+            task_id: "t".into(),
+            cron: cron.map(String::from),
+            interval_secs,
+            catch_up: CatchUpPolicy::Skip,
+            overlap: OverlapPolicy::Skip,
+            enabled: None,
+        }
+    }
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 5, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn validates_specs() {
+        assert!(validate(&request(Some("*/5 * * * *"), None)).is_ok());
+        assert!(validate(&request(None, Some(60))).is_ok());
+
+        assert!(validate(&request(Some("not cron"), None)).is_err());
+        assert!(validate(&request(Some("61 * * * *"), None)).is_err());
+        assert!(validate(&request(None, Some(0))).is_err());
+        assert!(validate(&request(None, Some(-5))).is_err());
+        assert!(validate(&request(None, None)).is_err());
+        assert!(validate(&request(Some("* * * * *"), Some(60))).is_err());
+    }
+
+    #[test]
+    fn interval_counts_from_after() {
+        let next = next_occurrence(None, Some(90), at(10, 0, 0)).unwrap();
+        assert_eq!(next, at(10, 1, 30));
+        assert!(next_occurrence(None, None, at(10, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn cron_is_strictly_after_and_whole_seconds() {
+        // Every minute lines up in any time zone the tests run in.
+        let after = at(10, 0, 0) + chrono::Duration::milliseconds(250);
+        assert_eq!(next_occurrence(Some("* * * * *"), None, after).unwrap(), at(10, 1, 0));
+        assert_eq!(next_occurrence(Some("* * * * *"), None, at(10, 1, 0)).unwrap(), at(10, 2, 0));
+        assert!(next_occurrence(Some("bogus"), None, after).is_err());
+    }
+
+    #[test]
+    fn late_checks_do_not_drift() {
+        // Checked 3s after the due time: the next run is still on the grid.
+        let next = following_occurrence(None, Some(60), at(10, 0, 0), at(10, 0, 3)).unwrap();
+        assert_eq!(next, at(10, 1, 0));
+        let next = following_occurrence(Some("* * * * *"), None, at(10, 0, 0), at(10, 0, 3)).unwrap();
+        assert_eq!(next, at(10, 1, 0));
+    }
+
+    #[test]
+    fn missed_due_times_collapse() {
+        // Down from 10:00 to 10:05:30: one pass handles 10:00, the next is 10:06.
+        let now = at(10, 5, 30);
+        assert_eq!(following_occurrence(None, Some(60), at(10, 0, 0), now).unwrap(), at(10, 6, 0));
+        assert_eq!(following_occurrence(Some("* * * * *"), None, at(10, 0, 0), now).unwrap(), at(10, 6, 0));
+        // Exactly on a later due time: that one counts as handled too.
+        assert_eq!(following_occurrence(None, Some(60), at(10, 0, 0), at(10, 2, 0)).unwrap(), at(10, 3, 0));
+    }
+}
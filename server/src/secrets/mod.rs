@@ -0,0 +1,278 @@
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::Duration;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sqlx::SqlitePool;
+use anyhow::{Result, anyhow, Context};
+use crate::core::models::Secret;
+
+const NONCE_LEN: usize = 12;
+
+/// What a secret value is replaced with in task output.
+pub const MASK: &[u8] = b"********";
+
+/// How long output held back by `Redactor` may wait for more before it is
+/// flushed anyway, with any secret in it masked.
+pub const REDACT_HOLD: Duration = Duration::from_millis(200);
+
+/// Named secrets, encrypted at rest in the `secrets` table.
+///
+/// Values are sealed with ChaCha20-Poly1305 under a key kept in a local
+/// file, outside the database. The secret's name is authenticated along with
+/// the value, so a ciphertext copied to another row won't decrypt.
+pub struct SecretStore {
+    pool: SqlitePool,
+    cipher: ChaCha20Poly1305,
+}
+
+/// Letters, digits, `_`, `-` and `.`, so names are safe in URLs and logs.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+impl SecretStore {
+    /// Loads the key from `key_file`, generating one readable only by the
+    /// server's user if the file doesn't exist yet.
+    pub fn open(pool: SqlitePool, key_file: &Path) -> Result<Self> {
+        let key = match std::fs::read(key_file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                let mut f = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(key_file)
+                    .with_context(|| format!("Failed to create secrets key {}", key_file.display()))?;
+                f.write_all(&key)?;
+                tracing::info!("Generated secrets key at {}", key_file.display());
+                key.to_vec()
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read secrets key {}", key_file.display())),
+        };
+        if key.len() != 32 {
+            return Err(anyhow!("Secrets key {} must be 32 bytes, found {}", key_file.display(), key.len()));
+        }
+        Ok(Self { pool, cipher: ChaCha20Poly1305::new(Key::from_slice(&key)) })
+    }
+
+    /// Names and timestamps only.
+    pub async fn list(&self) -> Result<Vec<Secret>> {
+        Ok(sqlx::query_as("SELECT name, created_at, updated_at FROM secrets ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    pub async fn exists(&self, name: &str) -> Result<bool> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT name FROM secrets WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    /// Create or replace a secret.
+    pub async fn set(&self, name: &str, value: &str) -> Result<()> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self.cipher
+            .encrypt(&nonce, Payload { msg: value.as_bytes(), aad: name.as_bytes() })
+            .map_err(|_| anyhow!("Failed to encrypt secret {}", name))?;
+        let mut blob = nonce.to_vec();
+        blob.extend(sealed);
+
+        sqlx::query(
+            "INSERT INTO secrets (name, value, created_at, updated_at) VALUES (?, ?, datetime('now'), datetime('now')) \
+             ON CONFLICT(name) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at"
+        )
+        .bind(name)
+        .bind(blob)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM secrets WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// The plaintext value, for injecting into a task at spawn.
+    pub async fn reveal(&self, name: &str) -> Result<String> {
+        let row: Option<(Vec<u8>,)> = sqlx::query_as("SELECT value FROM secrets WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        let (blob,) = row.ok_or_else(|| anyhow!("Unknown secret {}", name))?;
+        if blob.len() < NONCE_LEN {
+            return Err(anyhow!("Secret {} is corrupt", name));
+        }
+        let (nonce, sealed) = blob.split_at(NONCE_LEN);
+        let plain = self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad: name.as_bytes() })
+            .map_err(|_| anyhow!("Failed to decrypt secret {}; was the key file replaced?", name))?;
+        String::from_utf8(plain).context("Secret is not valid UTF-8")
+    }
+}
+
+/// Masks secret values in a byte stream that arrives in arbitrary chunks.
+///
+/// A chunk ending in what could be the start of a secret is held back until
+/// the next one shows whether it is, so a value split across two reads is
+/// still caught. If no next chunk comes within `REDACT_HOLD`, the caller
+/// flushes the tail so a prompt isn't stuck behind it.
+pub struct Redactor {
+    secrets: Vec<Vec<u8>>,
+    pending: Vec<u8>,
+    /// How much of `pending` a flush already let out as a mask.
+    masked: usize,
+}
+
+impl Redactor {
+    pub fn new(secrets: impl IntoIterator<Item = String>) -> Self {
+        let mut secrets: Vec<Vec<u8>> = secrets.into_iter()
+            .filter(|s| !s.is_empty())
+            .map(String::into_bytes)
+            .collect();
+        // Longest first, so a secret that contains another is masked whole.
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        Self { secrets, pending: Vec::new(), masked: 0 }
+    }
+
+    /// The redacted output that is safe to pass on so far.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+        if self.secrets.is_empty() {
+            return data.to_vec();
+        }
+        self.pending.extend_from_slice(data);
+        self.scan(false)
+    }
+
+    /// Whatever was held back, once the stream has ended or gone quiet.
+    ///
+    /// Complete secrets in it are masked, and so is a trailing start of one,
+    /// which may be a secret being printed slowly. That start stays held, so
+    /// if the rest of the secret follows it is swallowed too.
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.scan(true)
+    }
+
+    fn scan(&mut self, flush: bool) -> Vec<u8> {
+        let buf = &self.pending;
+        let mut out = Vec::with_capacity(buf.len());
+        let mut i = 0;
+        'scan: while i < buf.len() {
+            for s in &self.secrets {
+                if buf[i..].starts_with(s) {
+                    if i >= self.masked {
+                        out.extend_from_slice(MASK);
+                    }
+                    i += s.len();
+                    continue 'scan;
+                }
+                if s.starts_with(&buf[i..]) {
+                    if flush {
+                        if i >= self.masked {
+                            out.extend_from_slice(MASK);
+                        }
+                        self.masked = buf.len();
+                    }
+                    break 'scan;
+                }
+            }
+            if i >= self.masked {
+                out.push(buf[i]);
+            }
+            i += 1;
+        }
+        self.pending.drain(..i);
+        self.masked = self.masked.saturating_sub(i);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> Redactor {
+        Redactor::new(["hunter2".to_string(), "hunter2-admin".to_string()])
+    }
+
+    #[test]
+    fn masks_whole_secrets() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"pw=hunter2;"), b"pw=********;");
+        assert_eq!(r.feed(b"hunter2-admin!"), b"********!");
+        assert!(r.finish().is_empty());
+    }
+
+    #[test]
+    fn masks_a_secret_split_across_chunks() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"token: hun"), b"token: ");
+        assert_eq!(r.feed(b"te"), b"");
+        assert_eq!(r.feed(b"r2 ok\n"), b"******** ok\n");
+    }
+
+    #[test]
+    fn lets_go_of_a_false_start() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"hunt"), b"");
+        assert_eq!(r.feed(b"ing"), b"hunting");
+    }
+
+    #[test]
+    fn flushes_the_held_tail() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"$ hu"), b"$ ");
+        assert_eq!(r.finish(), MASK);
+        assert!(r.finish().is_empty());
+        // The rest of the secret doesn't get out after the flush.
+        assert_eq!(r.feed(b"nter2 ok"), b" ok");
+    }
+
+    #[test]
+    fn flushes_a_shorter_secret_held_for_a_longer_one() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"pw hunter2"), b"pw ");
+        assert_eq!(r.finish(), MASK);
+        assert_eq!(r.feed(b"-admin\n"), b"\n");
+
+        let mut r = redactor();
+        assert_eq!(r.feed(b"hunter2"), b"");
+        assert_eq!(r.finish(), MASK);
+        assert_eq!(r.feed(b" ok"), b" ok");
+    }
+
+    #[test]
+    fn masks_a_secret_printed_slowly_across_flushes() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"hun"), b"");
+        assert_eq!(r.finish(), MASK);
+        assert_eq!(r.feed(b"ter"), b"");
+        assert!(r.finish().is_empty());
+        assert_eq!(r.feed(b"2\nnext"), b"\nnext");
+        assert!(r.finish().is_empty());
+    }
+
+    #[test]
+    fn lets_go_of_a_false_start_after_a_flush() {
+        let mut r = redactor();
+        assert_eq!(r.feed(b"hu"), b"");
+        assert_eq!(r.finish(), MASK);
+        assert_eq!(r.feed(b"g me"), b"g me");
+        assert_eq!(r.feed(b" hunter2;"), b" ********;");
+    }
+
+    #[test]
+    fn passes_through_without_secrets() {
+        let mut r = Redactor::new(["".to_string()]);
+        assert_eq!(r.feed(b"h"), b"h");
+        assert!(r.finish().is_empty());
+    }
+}
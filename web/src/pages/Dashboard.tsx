@@ -1,4 +1,5 @@
 import { useEffect, useState, useRef } from "react";
+import { Link } from "react-router-dom";
 import ResourceChart from "../components/ResourceChart";
 import { Activity, Cpu, Database, Server } from "lucide-react";
 
@@ -6,14 +7,25 @@ interface SystemMetrics {
     cpu: number;
     memory: number; // used bytes
     memory_total: number;
-    gpu?: {
-       utilization: number;
-       memory_used: number;
-       memory_total: number;
-    };
+    gpus: Gpu[];
     tasks: any[];
 }
 
+interface Gpu {
+    index: number;
+    uuid: string;
+    name: string;
+    util: number;
+    mem_used: number; // MiB
+    mem_total: number;
+    temperature?: number;
+    power_draw?: number;
+    processes: { pid: number; mem_used: number; task_id?: string }[];
+}
+
+const averageUtil = (gpus: Gpu[]) =>
+    gpus.length ? gpus.reduce((sum, g) => sum + g.util, 0) / gpus.length : 0;
+
 export default function Dashboard() {
     const [history, setHistory] = useState<any[]>([]);
     const [latest, setLatest] = useState<SystemMetrics | null>(null);
@@ -32,7 +44,7 @@ export default function Dashboard() {
                     time: new Date().toISOString(),
                     cpu: metrics.cpu,
                     mem: (metrics.memory / metrics.memory_total) * 100,
-                    gpu: metrics.gpu ? metrics.gpu.utilization : 0, // Assuming GPU util is %
+                    gpu: averageUtil(metrics.gpus),
                 };
 
                 setHistory(prev => {
@@ -51,7 +63,8 @@ export default function Dashboard() {
     if (!latest) return <div className="p-8 text-gray-500">Connecting to system monitor...</div>;
 
     const memPercent = (latest.memory / latest.memory_total) * 100;
-    const gpuPercent = latest.gpu ? latest.gpu.utilization : 0;
+    const hasGpus = latest.gpus.length > 0;
+    const gpuPercent = averageUtil(latest.gpus);
 
     return (
         <div className="p-8">
@@ -78,7 +91,8 @@ export default function Dashboard() {
                     <div className="p-3 bg-green-500/10 rounded-full text-green-400"><Activity size={24} /></div>
                     <div>
                         <div className="text-gray-500 text-sm">GPU Utilization</div>
-                         <div className="text-2xl font-bold text-white">{latest.gpu ? `${gpuPercent}%` : "N/A"}</div>
+                         <div className="text-2xl font-bold text-white">{hasGpus ? `${gpuPercent.toFixed(0)}%` : "N/A"}</div>
+                         {latest.gpus.length > 1 && <div className="text-xs text-gray-600">average of {latest.gpus.length} GPUs</div>}
                     </div>
                 </div>
                  <div className="bg-gray-900 border border-gray-800 p-6 rounded-lg flex items-center gap-4">
@@ -94,8 +108,45 @@ export default function Dashboard() {
             <div className="grid grid-cols-1 lg:grid-cols-2 gap-6">
                 <ResourceChart data={history} dataKey="cpu" color="#60a5fa" title="Real-time CPU Usage" />
                 <ResourceChart data={history} dataKey="mem" color="#a855f7" title="Real-time Memory Usage" />
-                {latest.gpu && <ResourceChart data={history} dataKey="gpu" color="#4ade80" title="Real-time GPU Usage" />}
+                {hasGpus && <ResourceChart data={history} dataKey="gpu" color="#4ade80" title="Real-time GPU Usage" />}
             </div>
+
+            {/* Per-GPU breakdown */}
+            {hasGpus && (
+                <div className="mt-8 bg-gray-900 border border-gray-800 rounded-lg overflow-hidden">
+                    <table className="w-full text-sm">
+                        <thead className="text-gray-500 text-left border-b border-gray-800">
+                            <tr>
+                                <th className="px-4 py-2 font-medium">GPU</th>
+                                <th className="px-4 py-2 font-medium">Util</th>
+                                <th className="px-4 py-2 font-medium">Memory</th>
+                                <th className="px-4 py-2 font-medium">Temp</th>
+                                <th className="px-4 py-2 font-medium">Power</th>
+                                <th className="px-4 py-2 font-medium">Processes</th>
+                            </tr>
+                        </thead>
+                        <tbody className="text-gray-300 font-mono">
+                            {latest.gpus.map((g) => (
+                                <tr key={g.uuid} className="border-b border-gray-800/50 last:border-0">
+                                    <td className="px-4 py-2" title={g.uuid}>{g.index} <span className="text-gray-500 font-sans">{g.name}</span></td>
+                                    <td className="px-4 py-2">{g.util}%</td>
+                                    <td className="px-4 py-2">{(g.mem_used / 1024).toFixed(1)} / {(g.mem_total / 1024).toFixed(1)} GB</td>
+                                    <td className="px-4 py-2">{g.temperature != null ? `${g.temperature}°C` : "N/A"}</td>
+                                    <td className="px-4 py-2">{g.power_draw != null ? `${g.power_draw.toFixed(0)} W` : "N/A"}</td>
+                                    <td className="px-4 py-2 text-xs">
+                                        {g.processes.map((p) => (
+                                            <div key={p.pid}>
+                                                {p.task_id ? <Link to={`/tasks/${p.task_id}`} className="text-emerald-400 hover:underline">{p.pid}</Link> : p.pid}
+                                                <span className="text-gray-500"> {p.mem_used} MiB</span>
+                                            </div>
+                                        ))}
+                                    </td>
+                                </tr>
+                            ))}
+                        </tbody>
+                    </table>
+                </div>
+            )}
         </div>
     );
 }
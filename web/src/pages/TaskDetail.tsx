@@ -4,7 +4,7 @@ import { fetcher, API_BASE } from "../lib/api";
 import { Terminal } from "xterm";
 import { FitAddon } from "xterm-addon-fit";
 import "xterm/css/xterm.css";
-import { Play, Square, Activity, Cpu } from "lucide-react";
+import { Play, Square, Pause, Activity, Cpu, ListPlus } from "lucide-react";
 import clsx from "clsx";
 
 interface Task {
@@ -62,14 +62,25 @@ export default function TaskDetail() {
 
         ws.binaryType = 'arraybuffer';
 
+        // Binary frames are terminal bytes, text frames are JSON control messages.
+        const sendResize = () => {
+            if (ws.readyState === WebSocket.OPEN) {
+                ws.send(JSON.stringify({ type: 'resize', rows: term.rows, cols: term.cols }));
+            }
+        };
+
         ws.onopen = () => {
             term.writeln('\x1b[32m>>> Connected to task terminal\x1b[0m');
+            sendResize();
         };
 
         ws.onmessage = (ev) => {
             // Received data from PTY (backend sends broadcasted output)
             if (typeof ev.data === 'string') {
-                term.write(ev.data);
+                const msg = JSON.parse(ev.data);
+                if (msg.type === 'error') {
+                    term.writeln(`\r\n\x1b[31m>>> ${msg.message}\x1b[0m`);
+                }
             } else {
                 term.write(new Uint8Array(ev.data));
             }
@@ -80,11 +91,13 @@ export default function TaskDetail() {
         };
 
         // UI -> PTY
+        const encoder = new TextEncoder();
         term.onData((data) => {
             if (ws.readyState === WebSocket.OPEN) {
-                ws.send(data);
+                ws.send(encoder.encode(data));
             }
         });
+        term.onResize(sendResize);
 
         wsRef.current = ws;
 
@@ -107,6 +120,38 @@ export default function TaskDetail() {
         }
     };
 
+    const handleEnqueue = async () => {
+        try {
+            await fetch(`${API_BASE}/tasks/${id}/enqueue`, { method: "POST" });
+        } catch (e) {
+            console.error(e);
+        }
+    };
+
+    const handleStop = async () => {
+        try {
+            await fetch(`${API_BASE}/tasks/${id}/stop`, { method: "POST" });
+        } catch (e) {
+            console.error(e);
+        }
+    };
+
+    const handlePause = async () => {
+        try {
+            await fetch(`${API_BASE}/tasks/${id}/pause`, { method: "POST" });
+        } catch (e) {
+            console.error(e);
+        }
+    };
+
+    const handleResume = async () => {
+        try {
+            await fetch(`${API_BASE}/tasks/${id}/resume`, { method: "POST" });
+        } catch (e) {
+            console.error(e);
+        }
+    };
+
     if (!task) return <div className="p-8 text-gray-500">Loading task...</div>;
 
     return (
@@ -117,7 +162,8 @@ export default function TaskDetail() {
                     <h1 className="text-2xl font-bold text-white flex items-center gap-3">
                         {task.name}
                         <span className={clsx("text-xs px-2 py-0.5 rounded border font-mono", 
-                            task.status === "Running" ? "border-emerald-500/30 text-emerald-400 bg-emerald-500/10" : "border-gray-700 text-gray-500"
+                            task.status === "Running" ? "border-emerald-500/30 text-emerald-400 bg-emerald-500/10" :
+                            task.status === "Paused" ? "border-yellow-500/30 text-yellow-400 bg-yellow-500/10" : "border-gray-700 text-gray-500"
                         )}>
                             {task.status}
                         </span>
@@ -128,13 +174,28 @@ export default function TaskDetail() {
                     </div>
                 </div>
                 <div className="flex gap-4">
-                    {task.status !== "Running" && (
+                    {task.status !== "Running" && task.status !== "Paused" && task.status !== "Queued" && (
+                        <button onClick={handleEnqueue} className="flex items-center gap-2 bg-gray-800 hover:bg-gray-700 border border-gray-700 text-gray-200 px-4 py-2 rounded-md font-medium transition-colors">
+                            <ListPlus size={16} /> Queue
+                        </button>
+                    )}
+                    {task.status !== "Running" && task.status !== "Paused" && (
                         <button onClick={handleStart} className="flex items-center gap-2 bg-emerald-600 hover:bg-emerald-500 text-white px-4 py-2 rounded-md font-medium transition-colors">
                             <Play size={16} fill="currentColor" /> Start Task
                         </button>
                     )}
                     {task.status === "Running" && (
-                        <button className="flex items-center gap-2 bg-red-900/50 hover:bg-red-900/80 border border-red-800 text-red-200 px-4 py-2 rounded-md font-medium transition-colors">
+                        <button onClick={handlePause} className="flex items-center gap-2 bg-gray-800 hover:bg-gray-700 border border-gray-700 text-gray-200 px-4 py-2 rounded-md font-medium transition-colors">
+                            <Pause size={16} fill="currentColor" /> Pause
+                        </button>
+                    )}
+                    {task.status === "Paused" && (
+                        <button onClick={handleResume} className="flex items-center gap-2 bg-emerald-600 hover:bg-emerald-500 text-white px-4 py-2 rounded-md font-medium transition-colors">
+                            <Play size={16} fill="currentColor" /> Resume
+                        </button>
+                    )}
+                    {(task.status === "Running" || task.status === "Paused") && (
+                        <button onClick={handleStop} className="flex items-center gap-2 bg-red-900/50 hover:bg-red-900/80 border border-red-800 text-red-200 px-4 py-2 rounded-md font-medium transition-colors">
                             <Square size={16} fill="currentColor" /> Stop
                         </button>
                     )}
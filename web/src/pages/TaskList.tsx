@@ -29,8 +29,15 @@ export default function TaskList() {
     const getStatusColor = (status: string) => {
         switch (status) {
             case "Running": return "text-emerald-400";
+            case "Paused": return "text-yellow-400";
             case "Completed": return "text-blue-400";
             case "Failed": return "text-red-400";
+            case "Stopped": return "text-amber-400";
+            case "Queued": return "text-sky-400";
+            case "Skipped": return "text-gray-500";
+            case "Retrying": return "text-orange-400";
+            case "TimedOut": return "text-rose-400";
+            case "Lost": return "text-fuchsia-400";
             default: return "text-gray-400";
         }
     };
@@ -72,6 +79,7 @@ export default function TaskList() {
                                     <div className="flex items-center gap-4">
                                         <div className={clsx("px-3 py-1 rounded-full text-xs font-medium border", 
                                             task.status === "Running" ? "border-emerald-500/30 bg-emerald-500/10 text-emerald-400" : 
+                                            task.status === "Paused" ? "border-yellow-500/30 bg-yellow-500/10 text-yellow-400" :
                                             task.status === "Failed" ? "border-red-500/30 bg-red-500/10 text-red-400" :
                                             "border-gray-700 bg-gray-800 text-gray-400"
                                         )}>
@@ -1,13 +1,38 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { useNavigate } from "react-router-dom";
 import { API_BASE } from "../lib/api";
 import { ChevronRight } from "lucide-react";
 
+interface DiscoveredEnv {
+    env_type: string;
+    name: string;
+    path: string | null;
+    display_name: string | null;
+    language: string | null;
+}
+
+const BUILTIN_ENV_TYPES = ["shell", "conda", "mamba", "micromamba", "uv", "jupyter", "venv", "poetry", "pixi", "container"];
+
 export default function TaskNew() {
     const navigate = useNavigate();
+    const [customEnvTypes, setCustomEnvTypes] = useState<string[]>([]);
+    const [discovered, setDiscovered] = useState<DiscoveredEnv[]>([]);
+
+    useEffect(() => {
+        // Site-specific backends from the server's backends file
+        fetch(`${API_BASE}/envs/types`)
+            .then(res => res.json())
+            .then((types: string[]) => setCustomEnvTypes(types.filter(t => !BUILTIN_ENV_TYPES.includes(t))))
+            .catch(err => console.error(err));
+        fetch(`${API_BASE}/envs`)
+            .then(res => res.json())
+            .then(setDiscovered)
+            .catch(err => console.error(err));
+    }, []);
     const [formData, setFormData] = useState({
         name: "",
         command: "",
+        mode: "shell",
         env_type: "shell",
         env_name: "",
         cwd: ""
@@ -19,7 +44,13 @@ export default function TaskNew() {
             const res = await fetch(`${API_BASE}/tasks`, {
                 method: "POST",
                 headers: { "Content-Type": "application/json" },
-                body: JSON.stringify({ ...formData, args: [] })
+                // Blank fields mean unset, not an empty path or name.
+                body: JSON.stringify({
+                    ...formData,
+                    env_name: formData.env_name || null,
+                    cwd: formData.cwd || null,
+                    args: []
+                })
             });
             if (res.ok) {
                 navigate("/tasks");
@@ -57,6 +88,13 @@ export default function TaskNew() {
                             <option value="shell">System Shell (sh)</option>
                             <option value="conda">Conda Environment</option>
                             <option value="uv">UV Project</option>
+                            <option value="venv">Python virtualenv</option>
+                            <option value="poetry">Poetry Project</option>
+                            <option value="pixi">Pixi Project</option>
+                            <option value="container">Container Image</option>
+                            {customEnvTypes.map(t => (
+                                <option key={t} value={t}>{t}</option>
+                            ))}
                             <option value="jupyter">Jupyter Kernel</option>
                         </select>
                     </div>
@@ -69,9 +107,15 @@ export default function TaskNew() {
                             className="w-full bg-black border border-gray-700 rounded-md p-2.5 text-white outline-none"
                             value={formData.env_name}
                             onChange={e => setFormData({...formData, env_name: e.target.value})}
-                            placeholder={formData.env_type === "jupyter" ? "/path/to/kernel.json" : "base"}
-                            disabled={formData.env_type === "shell"}
+                            placeholder={formData.env_type === "jupyter" ? "/path/to/kernel.json" : formData.env_type === "venv" ? ".venv" : formData.env_type === "pixi" ? "default" : formData.env_type === "container" ? "python:3.12-slim" : "base"}
+                            disabled={formData.env_type === "shell" || formData.env_type === "poetry"}
+                            list="env-suggestions"
                         />
+                        <datalist id="env-suggestions">
+                            {discovered.filter(env => env.env_type === formData.env_type).map(env => (
+                                <option key={env.name} value={env.name}>{env.display_name ?? env.path ?? env.name}</option>
+                            ))}
+                        </datalist>
                     </div>
                 </div>
 
@@ -87,14 +131,42 @@ export default function TaskNew() {
                 </div>
 
                 <div>
-                    <label className="block text-sm font-medium text-gray-400 mb-2">Command</label>
-                    <textarea 
-                        required
-                        className="w-full bg-black border border-gray-700 rounded-md p-2.5 text-white font-mono text-sm outline-none h-32"
-                        value={formData.command}
-                        onChange={e => setFormData({...formData, command: e.target.value})}
-                        placeholder="python train.py --epochs 100"
-                    />
+                    <div className="flex items-center justify-between mb-2">
+                        <label className="block text-sm font-medium text-gray-400">
+                            {formData.mode === "notebook" ? "Notebook" : "Command"}
+                        </label>
+                        <select
+                            className="bg-black border border-gray-700 rounded-md px-2 py-1 text-sm text-white outline-none"
+                            value={formData.mode}
+                            onChange={e => setFormData({...formData, mode: e.target.value})}
+                        >
+                            <option value="shell">Shell command</option>
+                            <option value="notebook">Run notebook (.ipynb)</option>
+                        </select>
+                    </div>
+                    {formData.mode === "notebook" ? (
+                        <input
+                            type="text"
+                            required
+                            className="w-full bg-black border border-gray-700 rounded-md p-2.5 text-white font-mono text-sm outline-none"
+                            value={formData.command}
+                            onChange={e => setFormData({...formData, command: e.target.value})}
+                            placeholder="analysis.ipynb"
+                        />
+                    ) : (
+                        <textarea 
+                            required
+                            className="w-full bg-black border border-gray-700 rounded-md p-2.5 text-white font-mono text-sm outline-none h-32"
+                            value={formData.command}
+                            onChange={e => setFormData({...formData, command: e.target.value})}
+                            placeholder="python train.py --epochs 100"
+                        />
+                    )}
+                    {formData.mode === "notebook" && (
+                        <p className="text-xs text-gray-500 mt-2">
+                            Cells run headless; each run saves an executed copy next to the notebook.
+                        </p>
+                    )}
                 </div>
 
                 <div className="flex justify-end pt-4">